@@ -0,0 +1,166 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Field, Fields, GenericArgument, Index, LitInt, LitStr,
+    Member, PathArguments, Type};
+
+// 構造体のフィールドを順に比較する comparator を、rust_sort::sort_key::SortKey の実装として生成する。
+//
+//     #[derive(SortKey)]
+//     struct Student {
+//         #[sort(priority = 1)]
+//         last_name: String,
+//         #[sort(priority = 2, case_insensitive)]
+//         first_name: String,
+//         #[sort(order = "desc", nulls = "last")]
+//         age: Option<u8>,
+//     }
+//
+// priority を指定したフィールドを priority の小さい順に比較し、その後に残りのフィールドを宣言順に比較する。
+// 生成したコードは ::rust_sort のパスを使うので、rust_sort に依存しているクレートでしか使えない。
+
+/// フィールドの #[sort(...)] 属性
+struct FieldOptions {
+    descending: bool,
+    priority: Option<i64>,
+    /// Some(true) なら None を後ろ、Some(false) なら前に置く
+    nulls_last: Option<bool>,
+    case_insensitive: bool,
+    skip: bool,
+}
+
+#[proc_macro_derive(SortKey, attributes(sort))]
+pub fn derive_sort_key(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => return Err(syn::Error::new_spanned(&input.ident, "SortKey can only be derived for structs")),
+    };
+
+    // (priority, 宣言順, 比較する式)
+    let mut keys = Vec::new();
+    let members: Vec<Member> = match fields {
+        Fields::Named(named) => named.named.iter().map(|f| Member::Named(f.ident.clone().unwrap())).collect(),
+        Fields::Unnamed(unnamed) => (0..unnamed.unnamed.len()).map(|i| Member::Unnamed(Index::from(i))).collect(),
+        Fields::Unit => Vec::new(),
+    };
+    for (position, (field, member)) in fields.iter().zip(members).enumerate() {
+        let options = parse_options(field)?;
+        if options.skip {
+            continue;
+        }
+        keys.push((options.priority, position, compare_field(field, &member, &options)?));
+    }
+    // priority のないフィールドは、priority のあるフィールドの後ろに宣言順で並べる
+    keys.sort_by_key(|&(priority, position, _)| (priority.is_none(), priority, position));
+    let comparisons = keys.into_iter().map(|(_, _, comparison)| comparison);
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::rust_sort::sort_key::SortKey for #name #ty_generics #where_clause {
+            fn sort_key_cmp(&self, other: &Self) -> ::core::cmp::Ordering {
+                ::core::cmp::Ordering::Equal
+                    #(.then_with(|| #comparisons))*
+            }
+        }
+    })
+}
+
+fn parse_options(field: &Field) -> syn::Result<FieldOptions> {
+    let mut options = FieldOptions {
+        descending: false,
+        priority: None,
+        nulls_last: None,
+        case_insensitive: false,
+        skip: false,
+    };
+
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("sort")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("order") {
+                let value: LitStr = meta.value()?.parse()?;
+                options.descending = match value.value().as_str() {
+                    "asc" => false,
+                    "desc" => true,
+                    _ => return Err(syn::Error::new_spanned(value, "expected \"asc\" or \"desc\"")),
+                };
+            } else if meta.path.is_ident("priority") {
+                let value: LitInt = meta.value()?.parse()?;
+                options.priority = Some(value.base10_parse()?);
+            } else if meta.path.is_ident("nulls") {
+                let value: LitStr = meta.value()?.parse()?;
+                options.nulls_last = match value.value().as_str() {
+                    "first" => Some(false),
+                    "last" => Some(true),
+                    _ => return Err(syn::Error::new_spanned(value, "expected \"first\" or \"last\"")),
+                };
+            } else if meta.path.is_ident("case_insensitive") {
+                options.case_insensitive = true;
+            } else if meta.path.is_ident("skip") {
+                options.skip = true;
+            } else {
+                return Err(meta.error("unknown sort attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(options)
+}
+
+/// self と other の member を比較する式
+fn compare_field(field: &Field, member: &Member, options: &FieldOptions) -> syn::Result<TokenStream2> {
+    // Option の中身同士、またはフィールド同士を比較する comparator
+    let inner = if options.case_insensitive {
+        quote!(::rust_sort::sort_key::cmp_case_insensitive(a, b))
+    } else {
+        quote!(::core::cmp::Ord::cmp(a, b))
+    };
+    let inner = if options.descending {
+        quote!(#inner.reverse())
+    } else {
+        inner
+    };
+
+    let is_option = is_option(&field.ty);
+    if options.nulls_last.is_some() && !is_option {
+        return Err(syn::Error::new_spanned(&field.ty, "nulls can only be used on Option fields"));
+    }
+
+    if is_option && (options.nulls_last.is_some() || options.case_insensitive) {
+        // 指定がなければ Option の Ord と同じく None を最小の値とみなす (昇順なら前、降順なら後ろ)
+        let nulls_last = options.nulls_last.unwrap_or(options.descending);
+        Ok(quote! {
+            ::rust_sort::sort_key::cmp_option(&self.#member, &other.#member, #nulls_last, |a, b| #inner)
+        })
+    } else {
+        Ok(quote! {
+            {
+                let (a, b) = (&self.#member, &other.#member);
+                #inner
+            }
+        })
+    }
+}
+
+/// 型が Option<T> か (パスの最後が Option で、型引数が 1 つ)
+fn is_option(ty: &Type) -> bool {
+    let path = match ty {
+        Type::Path(path) if path.qself.is_none() => &path.path,
+        _ => return false,
+    };
+    match path.segments.last() {
+        Some(segment) if segment.ident == "Option" => match &segment.arguments {
+            PathArguments::AngleBracketed(args) =>
+                args.args.len() == 1 && matches!(args.args[0], GenericArgument::Type(_)),
+            _ => false,
+        },
+        _ => false,
+    }
+}
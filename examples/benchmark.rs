@@ -1,11 +1,9 @@
-use num_cpus;
-
-use rust_sort::SortOrder;
-// マルチスレッド非対応の sort
-use rust_sort::third::sort as seq_sort;
-// マルチスレッド対応の sort
-use rust_sort::fourth::sort as par_sort;
-use rust_sort::utils::{is_sorted, new_u32_vec};
+use rust_sort::{SortError, SortOrder};
+use rust_sort::leaf::{LeafConfig, LeafSort};
+use rust_sort::network::Network;
+use rust_sort::sorter::Registry;
+use rust_sort::third::{self, sort_by_with_leaf};
+use rust_sort::utils::{is_sorted, new_bitonic_u32_vec, new_u32_vec};
 
 use std::{env, f64};
 use std::str::FromStr;
@@ -22,7 +20,7 @@ fn main() {
         // コマンドライン引数の指定がない場合は help を表示
         eprintln!(
             "Usage: {} <number of elements in bits>",
-            env::args().nth(0).unwrap());
+            env::args().next().unwrap());
     }
     std::process::exit(1);
 }
@@ -43,22 +41,80 @@ fn run_sorts(bits: u32) {
         num_cpus::get()
     );
 
-    // 順次 sort
-    let seq_duration = timed_sort(&seq_sort, len ,"seq_sort");
+    let registry = Registry::<u32>::with_all();
+
+    // 順次 sort (マルチスレッド非対応の third)
+    let third = registry.get("third").unwrap();
+    let seq_duration = timed_sort(|x| third.sort(x, &SortOrder::Ascending), len, "seq_sort");
 
-    // 並列 sort
-    let par_duration = timed_sort(&par_sort, len, "par_sort");
+    // 並列 sort (マルチスレッド対応の fourth)
+    let fourth = registry.get("fourth").unwrap();
+    let par_duration = timed_sort(|x| fourth.sort(x, &SortOrder::Ascending), len, "par_sort");
 
     println!("speed up: {:.2}x", seq_duration / par_duration);
+
+    // leaf (小さいブロックを挿入ソート / ソーティングネットワークでまとめてソートする) の効果
+    let no_leaf_duration = timed_sort(
+        |x| sort_by_with_leaf(x, &|a, b| a.cmp(b), &LeafConfig::disabled()),
+        len, "seq_sort (no leaf)");
+    let leaves = [
+        LeafConfig { size: 8, sort: LeafSort::Insertion },
+        LeafConfig { size: 16, sort: LeafSort::Insertion },
+        LeafConfig { size: 32, sort: LeafSort::Insertion },
+        LeafConfig { size: 8, sort: LeafSort::Network },
+        LeafConfig { size: 16, sort: LeafSort::Network },
+    ];
+    for leaf in leaves.iter() {
+        let name = format!("seq_sort (leaf: {:?} <= {})", leaf.sort, leaf.size);
+        let duration = timed_sort(|x| sort_by_with_leaf(x, &|a, b| a.cmp(b), leaf), len, &name);
+        println!("speed up: {:.2}x", no_leaf_duration / duration);
+    }
+
+    // sort_by は入力が bitonic 列かどうかを先頭から調べ、bitonic 列ならマージ段だけでソートする。
+    // network だけを実行する sort_by_with_leaf と比べて、ランダムな入力での判定のコストと bitonic 列での効果を計る
+    let leaf = LeafConfig::default();
+    let network_duration = timed_sort(|x| sort_by_with_leaf(x, &|a, b| a.cmp(b), &leaf), len, "seq_sort (network only, random)");
+    let sort_duration = timed_sort(|x| third::sort(x, &SortOrder::Ascending), len, "seq_sort (random)");
+    println!("added cost: {:.1}%", (sort_duration / network_duration - 1.0) * 100.0);
+    let bitonic = new_bitonic_u32_vec(len, len / 2);
+    let network_duration = timed_sort_input(|x| sort_by_with_leaf(x, &|a, b| a.cmp(b), &leaf), bitonic.clone(), "seq_sort (network only, bitonic)");
+    let sort_duration = timed_sort_input(|x| third::sort(x, &SortOrder::Ascending), bitonic, "seq_sort (bitonic)");
+    println!("speed up: {:.2}x", network_duration / sort_duration);
+
+    // ネットワークの種類ごとの比較器の数と、段ごとに並列実行した場合の時間。
+    // Network は比較器をすべてメモリに持つので、要素数が大きい場合は作らない
+    if len <= 1 << 16 {
+        let networks = [
+            ("bitonic", Network::bitonic(len)),
+            ("odd_even", Network::odd_even_merge(len)),
+            ("pairwise", Network::pairwise(len)),
+        ];
+        for (name, network) in networks.iter() {
+            println!("{}: {} comparators, depth {}", name, network.size(), network.depth());
+        }
+    }
+
+    // first は 2 の冪乗の長さしか扱えないが、len は 2 の冪乗なのでレジストリのすべての sorter で計れる。
+    // third, fourth は上で計ったので飛ばす
+    for sorter in registry.iter().filter(|s| !matches!(s.name(), "third" | "fourth")) {
+        timed_sort(|x| sorter.sort(x, &SortOrder::Ascending), len, sorter.name());
+    }
+}
+
+fn timed_sort<F>(sorter: F, len: usize, name: &str) -> f64
+    where F: Fn(&mut [u32]) -> Result<(), SortError>,
+{
+    timed_sort_input(sorter, new_u32_vec(len), name)
 }
 
-fn timed_sort<F>(sorter: &F, len: usize, name: &str) -> f64 
-    where F: Fn(&mut [u32], &SortOrder) -> Result<(), String>,
+/// 入力 x を指定してソートにかかった時間を計る
+fn timed_sort_input<F>(sorter: F, mut x: Vec<u32>, name: &str) -> f64
+    where F: Fn(&mut [u32]) -> Result<(), SortError>,
 {
-    let mut x = new_u32_vec(len);
+    let len = x.len();
 
     let start = Instant::now();
-    sorter(&mut x, &SortOrder::Ascending).expect("Filed to sort.");
+    sorter(&mut x).expect("Filed to sort.");
     let dur = start.elapsed();
 
     let nano_secs = dur.subsec_nanos() as f64 + dur.as_secs() as f64 * 1e9_f64;
@@ -0,0 +1,268 @@
+use super::{SortError, SortOrder};
+use std::cmp::Ordering;
+
+// 要素数がコンパイル時に決まっている配列 [T; N] を、その N 用のソーティングネットワークでソートする。
+// 比較器の並びは leaf::network(N) と同じもの (2..=8 と 16 は比較回数が最小のネットワーク、
+// 9..=15 は 16 要素のネットワークから n 以上のインデックスに触れる比較器を取り除いたもの) を
+// N ごとに書き出しておき、マクロで比較器 1 つにつき compare_and_swap の呼び出し 1 つに展開する。
+// ループもインデックスの表引きもない直線的なコードになる。書き出した表が leaf の表とずれていないことはテストで確かめる。
+// ネットワークがない N (MAX_NETWORK_SIZE より大きい N) は SortArray が実装されていないので、
+// sort_array を呼ぶとコンパイルエラーになる。
+
+/// 書き下したソーティングネットワークでソートできる配列。[T; N] (N = 0..=16) に実装されている
+pub trait SortArray<T> {
+    /// comparator の順に並べ替える
+    fn sort_network_by<F>(&mut self, comparator: &F)
+        where F: Fn(&T, &T) -> Ordering;
+}
+
+/// 配列 x を order の順にソートする
+///
+/// x の要素数 N はコンパイル時に決まり、N 用のネットワークを書き下したコードが使われる。
+/// N が leaf::MAX_NETWORK_SIZE より大きい場合はコンパイルできない
+pub fn sort_array<T: Ord, const N: usize>(x: &mut [T; N], order: &SortOrder) -> Result<(), SortError>
+    where [T; N]: SortArray<T>
+{
+    match *order {
+        SortOrder::Ascending  => sort_array_by(x, &|a, b| a.cmp(b)),
+        SortOrder::Descending => sort_array_by(x, &|a, b| b.cmp(a)),
+    }
+}
+
+pub fn sort_array_by<T, F, const N: usize>(x: &mut [T; N], comparator: &F) -> Result<(), SortError>
+    where [T; N]: SortArray<T>,
+        F: Fn(&T, &T) -> Ordering,
+{
+    x.sort_network_by(comparator);
+    Ok(())
+}
+
+#[inline(always)]
+fn compare_and_swap<T, F>(x: &mut [T], i: usize, j: usize, comparator: &F)
+    where F: Fn(&T, &T) -> Ordering
+{
+    if comparator(&x[i], &x[j]) == Ordering::Greater {
+        x.swap(i, j);
+    }
+}
+
+/// 要素数ごとに、並べた比較器 (i, j) をそのまま compare_and_swap の呼び出しに展開して配列への SortArray の実装を作る
+macro_rules! impl_sort_array {
+    ($($n:literal => [$(($i:literal, $j:literal)),* $(,)?],)*) => {$(
+        impl<T> SortArray<T> for [T; $n] {
+            #[inline]
+            #[allow(unused_variables)] // 0 要素と 1 要素は比較器がなく comparator を使わない
+            fn sort_network_by<F>(&mut self, comparator: &F)
+                where F: Fn(&T, &T) -> Ordering
+            {
+                $(compare_and_swap(self, $i, $j, comparator);)*
+            }
+        }
+    )*};
+}
+
+// leaf::network(N) と同じ並び。変えるときは leaf の表と合わせること (same_comparators_as_leaf で確かめる)
+impl_sort_array! {
+    0 => [],
+    1 => [],
+    2 => [(0, 1)],
+    3 => [(0, 2), (0, 1), (1, 2)],
+    4 => [(0, 2), (1, 3), (0, 1), (2, 3), (1, 2)],
+    5 => [
+        (0, 3), (1, 4),
+        (0, 2), (1, 3),
+        (0, 1), (2, 4),
+        (1, 2), (3, 4),
+        (2, 3),
+    ],
+    6 => [
+        (0, 5), (1, 3), (2, 4),
+        (1, 2), (3, 4),
+        (0, 3), (2, 5),
+        (0, 1), (2, 3), (4, 5),
+        (1, 2), (3, 4),
+    ],
+    7 => [
+        (0, 6), (2, 3), (4, 5),
+        (0, 2), (1, 4), (3, 6),
+        (0, 1), (2, 5), (3, 4),
+        (1, 2), (4, 6),
+        (2, 3), (4, 5),
+        (1, 2), (3, 4), (5, 6),
+    ],
+    8 => [
+        (0, 2), (1, 3), (4, 6), (5, 7),
+        (0, 4), (1, 5), (2, 6), (3, 7),
+        (0, 1), (2, 3), (4, 5), (6, 7),
+        (2, 4), (3, 5),
+        (1, 4), (3, 6),
+        (1, 2), (3, 4), (5, 6),
+    ],
+    9 => [
+        (4, 8), (5, 6),
+        (0, 5), (1, 7), (3, 4),
+        (0, 1), (2, 3), (4, 5), (6, 8),
+        (0, 2), (1, 3), (6, 7),
+        (1, 2), (4, 6), (5, 7),
+        (1, 4), (2, 6), (5, 8),
+        (2, 4), (3, 6),
+        (3, 5), (6, 8),
+        (3, 4), (5, 6), (7, 8),
+        (6, 7),
+    ],
+    10 => [
+        (4, 8), (5, 6),
+        (0, 5), (1, 7), (2, 9), (3, 4),
+        (0, 1), (2, 3), (4, 5), (6, 8), (7, 9),
+        (0, 2), (1, 3), (6, 7), (8, 9),
+        (1, 2), (4, 6), (5, 7),
+        (1, 4), (2, 6), (5, 8),
+        (2, 4), (3, 6),
+        (3, 5), (6, 8), (7, 9),
+        (3, 4), (5, 6), (7, 8),
+        (6, 7), (8, 9),
+    ],
+    11 => [
+        (4, 8), (5, 6), (9, 10),
+        (0, 5), (1, 7), (2, 9), (3, 4),
+        (0, 1), (2, 3), (4, 5), (6, 8), (7, 9),
+        (0, 2), (1, 3), (4, 10), (6, 7), (8, 9),
+        (1, 2), (4, 6), (5, 7), (8, 10),
+        (1, 4), (2, 6), (5, 8), (7, 10),
+        (2, 4), (3, 6),
+        (3, 5), (6, 8), (7, 9),
+        (3, 4), (5, 6), (7, 8), (9, 10),
+        (6, 7), (8, 9),
+    ],
+    12 => [
+        (4, 8), (5, 6), (7, 11), (9, 10),
+        (0, 5), (1, 7), (2, 9), (3, 4),
+        (0, 1), (2, 3), (4, 5), (6, 8), (7, 9), (10, 11),
+        (0, 2), (1, 3), (4, 10), (5, 11), (6, 7), (8, 9),
+        (1, 2), (4, 6), (5, 7), (8, 10), (9, 11),
+        (1, 4), (2, 6), (5, 8), (7, 10),
+        (2, 4), (3, 6),
+        (3, 5), (6, 8), (7, 9),
+        (3, 4), (5, 6), (7, 8), (9, 10),
+        (6, 7), (8, 9),
+    ],
+    13 => [
+        (1, 12), (4, 8), (5, 6), (7, 11), (9, 10),
+        (0, 5), (1, 7), (2, 9), (3, 4), (11, 12),
+        (0, 1), (2, 3), (4, 5), (6, 8), (7, 9), (10, 11),
+        (0, 2), (1, 3), (4, 10), (5, 11), (6, 7), (8, 9),
+        (1, 2), (3, 12), (4, 6), (5, 7), (8, 10), (9, 11),
+        (1, 4), (2, 6), (5, 8), (7, 10),
+        (2, 4), (3, 6), (9, 12),
+        (3, 5), (6, 8), (7, 9), (10, 12),
+        (3, 4), (5, 6), (7, 8), (9, 10), (11, 12),
+        (6, 7), (8, 9),
+    ],
+    14 => [
+        (0, 13), (1, 12), (4, 8), (5, 6), (7, 11), (9, 10),
+        (0, 5), (1, 7), (2, 9), (3, 4), (6, 13), (11, 12),
+        (0, 1), (2, 3), (4, 5), (6, 8), (7, 9), (10, 11), (12, 13),
+        (0, 2), (1, 3), (4, 10), (5, 11), (6, 7), (8, 9),
+        (1, 2), (3, 12), (4, 6), (5, 7), (8, 10), (9, 11),
+        (1, 4), (2, 6), (5, 8), (7, 10), (9, 13),
+        (2, 4), (3, 6), (9, 12), (11, 13),
+        (3, 5), (6, 8), (7, 9), (10, 12),
+        (3, 4), (5, 6), (7, 8), (9, 10), (11, 12),
+        (6, 7), (8, 9),
+    ],
+    15 => [
+        (0, 13), (1, 12), (3, 14), (4, 8), (5, 6), (7, 11), (9, 10),
+        (0, 5), (1, 7), (2, 9), (3, 4), (6, 13), (8, 14), (11, 12),
+        (0, 1), (2, 3), (4, 5), (6, 8), (7, 9), (10, 11), (12, 13),
+        (0, 2), (1, 3), (4, 10), (5, 11), (6, 7), (8, 9), (12, 14),
+        (1, 2), (3, 12), (4, 6), (5, 7), (8, 10), (9, 11), (13, 14),
+        (1, 4), (2, 6), (5, 8), (7, 10), (9, 13), (11, 14),
+        (2, 4), (3, 6), (9, 12), (11, 13),
+        (3, 5), (6, 8), (7, 9), (10, 12),
+        (3, 4), (5, 6), (7, 8), (9, 10), (11, 12),
+        (6, 7), (8, 9),
+    ],
+    16 => [
+        (0, 13), (1, 12), (2, 15), (3, 14), (4, 8), (5, 6), (7, 11), (9, 10),
+        (0, 5), (1, 7), (2, 9), (3, 4), (6, 13), (8, 14), (10, 15), (11, 12),
+        (0, 1), (2, 3), (4, 5), (6, 8), (7, 9), (10, 11), (12, 13), (14, 15),
+        (0, 2), (1, 3), (4, 10), (5, 11), (6, 7), (8, 9), (12, 14), (13, 15),
+        (1, 2), (3, 12), (4, 6), (5, 7), (8, 10), (9, 11), (13, 14),
+        (1, 4), (2, 6), (5, 8), (7, 10), (9, 13), (11, 14),
+        (2, 4), (3, 6), (9, 12), (11, 13),
+        (3, 5), (6, 8), (7, 9), (10, 12),
+        (3, 4), (5, 6), (7, 8), (9, 10), (11, 12),
+        (6, 7), (8, 9),
+    ],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sort_array, sort_array_by, SortArray};
+    use crate::leaf;
+    use crate::third;
+    use crate::SortOrder::{Ascending as Asc, Descending as Desc};
+    use crate::utils::new_u32_arrays;
+    use std::cell::RefCell;
+    use std::cmp::Ordering;
+
+    /// N 要素の配列に書き下した比較器が leaf::network(N) と同じ並びであることと、
+    /// いくつかの入力を third::sort と同じ順に並べられることを確かめる
+    fn check<const N: usize>()
+        where [usize; N]: SortArray<usize>,
+            [u32; N]: SortArray<u32>,
+    {
+        // 比較器を記録するだけで交換しない comparator を使うと、要素の値がそのままインデックスになる
+        let mut indices = [0; N];
+        for (i, v) in indices.iter_mut().enumerate() {
+            *v = i;
+        }
+        let recorded = RefCell::new(Vec::new());
+        assert_eq!(sort_array_by(&mut indices, &|a: &usize, b: &usize| {
+            recorded.borrow_mut().push((*a, *b));
+            Ordering::Equal
+        }), Ok(()));
+        assert_eq!(recorded.into_inner(), leaf::network(N).collect::<Vec<_>>(), "N = {}", N);
+
+        for input in new_u32_arrays::<N>(20) {
+            for order in &[Asc, Desc] {
+                let mut expected = input.to_vec();
+                assert_eq!(third::sort(&mut expected, order), Ok(()));
+
+                let mut x = input;
+                assert_eq!(sort_array(&mut x, order), Ok(()));
+                assert_eq!(x.to_vec(), expected, "N = {}", N);
+            }
+        }
+    }
+
+    #[test]
+    fn same_comparators_as_leaf() {
+        check::<0>();
+        check::<1>();
+        check::<2>();
+        check::<3>();
+        check::<4>();
+        check::<5>();
+        check::<6>();
+        check::<7>();
+        check::<8>();
+        check::<9>();
+        check::<10>();
+        check::<11>();
+        check::<12>();
+        check::<13>();
+        check::<14>();
+        check::<15>();
+        check::<16>();
+    }
+
+    #[test]
+    fn sort_str_array() {
+        let mut x = ["Rust", "is", "fast", "and", "memory-efficient", "with", "no", "GC"];
+
+        assert_eq!(sort_array(&mut x, &Desc), Ok(()));
+
+        assert_eq!(x, ["with", "no", "memory-efficient", "is", "fast", "and", "Rust", "GC"]);
+    }
+}
@@ -0,0 +1,36 @@
+use rust_sort::codegen::{generate, Kind, Language};
+
+use std::env;
+use std::str::FromStr;
+
+fn main() {
+    // sortgen <要素数> [ネットワークの種類] [言語] [要素の型]
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!(
+            "Usage: {} <number of elements> [bitonic|odd_even_merge|pairwise] [rust|c|wgsl|glsl] [element type]",
+            args[0]);
+        std::process::exit(1);
+    }
+
+    let n = usize::from_str(&args[1]).expect("error parsing the number of elements");
+    let kind: Kind = args.get(2).map_or(Ok(Kind::Bitonic), |s| s.parse()).unwrap_or_else(|e| exit_with(&e));
+    let language: Language = args.get(3).map_or(Ok(Language::Rust), |s| s.parse()).unwrap_or_else(|e| exit_with(&e));
+    let element_type = args.get(4).map_or_else(|| default_element_type(language), |s| s.as_str());
+
+    let name = format!("{}_{}", kind.name(), n);
+    print!("{}", generate(&kind.network(n), language, &name, element_type));
+}
+
+fn default_element_type(language: Language) -> &'static str {
+    match language {
+        Language::Rust => "",
+        Language::C | Language::Glsl => "float",
+        Language::Wgsl => "f32",
+    }
+}
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
@@ -0,0 +1,193 @@
+use crate::network::Network;
+use std::fmt::Write;
+use std::str::FromStr;
+
+// 要素数が決まっている場所では、ソーティングネットワークの比較器をループせずにすべて書き下した
+// (アンロールした) コードの方が速い。そのコードを Network から生成する。
+// 生成するのは 1 つの関数だけで、比較器 (i, j) ごとに「小さい方を i、大きい方を j に置く」処理を 1 行ずつ並べる。
+// 段の区切りにはコメントを入れるので、どの比較器が並列に実行できるかは生成したコードからも読める。
+
+/// 生成するネットワークの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Bitonic,
+    OddEvenMerge,
+    Pairwise,
+}
+
+impl Kind {
+    pub fn network(&self, n: usize) -> Network {
+        match *self {
+            Kind::Bitonic => Network::bitonic(n),
+            Kind::OddEvenMerge => Network::odd_even_merge(n),
+            Kind::Pairwise => Network::pairwise(n),
+        }
+    }
+
+    /// 関数名などに使う名前
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Kind::Bitonic => "bitonic",
+            Kind::OddEvenMerge => "odd_even_merge",
+            Kind::Pairwise => "pairwise",
+        }
+    }
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bitonic" => Ok(Kind::Bitonic),
+            "odd_even_merge" | "odd_even" => Ok(Kind::OddEvenMerge),
+            "pairwise" => Ok(Kind::Pairwise),
+            _ => Err(format!("unknown network kind: {}", s)),
+        }
+    }
+}
+
+/// 生成するコードの言語
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    /// `fn name<T: Ord>(x: &mut [T; N])`
+    Rust,
+    /// `void name(TYPE *x)`
+    C,
+    /// `fn name(x: ptr<function, array<TYPE, N>>)` (WebGPU のシェーダー)
+    Wgsl,
+    /// `void name(inout TYPE x[N])` (OpenGL / Vulkan のシェーダー)
+    Glsl,
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rust" => Ok(Language::Rust),
+            "c" => Ok(Language::C),
+            "wgsl" => Ok(Language::Wgsl),
+            "glsl" => Ok(Language::Glsl),
+            _ => Err(format!("unknown language: {}", s)),
+        }
+    }
+}
+
+/// network を昇順にソートする関数 name のソースコードを生成する
+///
+/// element_type は C, WGSL, GLSL での要素の型 (例: "float", "u32")。Rust では T: Ord のジェネリクスにするので使わない
+pub fn generate(network: &Network, language: Language, name: &str, element_type: &str) -> String {
+    let n = network.n();
+    let mut code = String::new();
+
+    // 関数の先頭
+    let summary = format!("{} 要素のソーティングネットワーク (比較器 {} 個、{} 段)。sortgen で生成",
+        n, network.size(), network.depth());
+    match language {
+        Language::Rust => {
+            writeln!(code, "/// {}", summary).unwrap();
+            writeln!(code, "pub fn {}<T: Ord>(x: &mut [T; {}]) {{", name, n).unwrap();
+        },
+        Language::C => {
+            writeln!(code, "/* {} */", summary).unwrap();
+            writeln!(code, "void {}({} *x) {{", name, element_type).unwrap();
+            writeln!(code, "    {} a, b;", element_type).unwrap();
+        },
+        Language::Wgsl => {
+            writeln!(code, "// {}", summary).unwrap();
+            writeln!(code, "fn {}(x: ptr<function, array<{}, {}>>) {{", name, element_type, n).unwrap();
+        },
+        Language::Glsl => {
+            writeln!(code, "// {}", summary).unwrap();
+            writeln!(code, "void {}(inout {} x[{}]) {{", name, element_type, n).unwrap();
+            writeln!(code, "    {} a, b;", element_type).unwrap();
+        },
+    }
+
+    // 比較器を 1 行ずつ
+    for (depth, stage) in network.stages().iter().enumerate() {
+        match language {
+            Language::C => writeln!(code, "    /* {} 段目 */", depth + 1).unwrap(),
+            _ => writeln!(code, "    // {} 段目", depth + 1).unwrap(),
+        }
+        for &(i, j) in stage.iter() {
+            let line = match language {
+                Language::Rust =>
+                    format!("if x[{j}] < x[{i}] {{ x.swap({i}, {j}); }}", i = i, j = j),
+                Language::C =>
+                    format!("a = x[{i}]; b = x[{j}]; if (b < a) {{ x[{i}] = b; x[{j}] = a; }}", i = i, j = j),
+                Language::Wgsl => format!(
+                    "{{ let a = (*x)[{i}]; let b = (*x)[{j}]; (*x)[{i}] = min(a, b); (*x)[{j}] = max(a, b); }}",
+                    i = i, j = j),
+                Language::Glsl =>
+                    format!("a = x[{i}]; b = x[{j}]; x[{i}] = min(a, b); x[{j}] = max(a, b);", i = i, j = j),
+            };
+            writeln!(code, "    {}", line).unwrap();
+        }
+    }
+
+    code.push_str("}\n");
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate, Kind, Language};
+    use crate::network::Network;
+
+    #[test]
+    fn rust_source() {
+        let code = generate(&Network::bitonic(3), Language::Rust, "sort3", "");
+        assert_eq!(code, "\
+/// 3 要素のソーティングネットワーク (比較器 3 個、3 段)。sortgen で生成
+pub fn sort3<T: Ord>(x: &mut [T; 3]) {
+    // 1 段目
+    if x[2] < x[1] { x.swap(1, 2); }
+    // 2 段目
+    if x[2] < x[0] { x.swap(0, 2); }
+    // 3 段目
+    if x[1] < x[0] { x.swap(0, 1); }
+}
+");
+    }
+
+    #[test]
+    fn shader_and_c_sources() {
+        let network = Network::from_comparators(2, vec![(0, 1)]);
+
+        assert_eq!(generate(&network, Language::C, "sort2", "float"), "\
+/* 2 要素のソーティングネットワーク (比較器 1 個、1 段)。sortgen で生成 */
+void sort2(float *x) {
+    float a, b;
+    /* 1 段目 */
+    a = x[0]; b = x[1]; if (b < a) { x[0] = b; x[1] = a; }
+}
+");
+        assert_eq!(generate(&network, Language::Wgsl, "sort2", "f32"), "\
+// 2 要素のソーティングネットワーク (比較器 1 個、1 段)。sortgen で生成
+fn sort2(x: ptr<function, array<f32, 2>>) {
+    // 1 段目
+    { let a = (*x)[0]; let b = (*x)[1]; (*x)[0] = min(a, b); (*x)[1] = max(a, b); }
+}
+");
+        assert_eq!(generate(&network, Language::Glsl, "sort2", "uint"), "\
+// 2 要素のソーティングネットワーク (比較器 1 個、1 段)。sortgen で生成
+void sort2(inout uint x[2]) {
+    uint a, b;
+    // 1 段目
+    a = x[0]; b = x[1]; x[0] = min(a, b); x[1] = max(a, b);
+}
+");
+    }
+
+    #[test]
+    fn parse_kind_and_language() {
+        assert_eq!("odd_even".parse(), Ok(Kind::OddEvenMerge));
+        assert_eq!("pairwise".parse(), Ok(Kind::Pairwise));
+        assert_eq!("bubble".parse::<Kind>(), Err("unknown network kind: bubble".to_string()));
+        assert_eq!("wgsl".parse(), Ok(Language::Wgsl));
+        assert!("java".parse::<Language>().is_err());
+        assert_eq!(Kind::Bitonic.network(8), Network::bitonic(8));
+    }
+}
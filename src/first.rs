@@ -1,9 +1,21 @@
-pub fn sort(x: &mut [u32], up: bool) {
+use crate::{SortError, SortOrder};
+use crate::sorter::{Capabilities, Sorter};
+use std::cmp::Ordering;
+
+pub fn sort(x: &mut [u32], up: bool) -> Result<(), SortError> {
+    if x.len() > 1 && !x.len().is_power_of_two() {
+        return Err(SortError::InvalidLength { len: x.len() });
+    }
+    do_sort(x, up);
+    Ok(())
+}
+
+fn do_sort(x: &mut [u32], up: bool) {
     // [u32] はスライス。Vec とはまた異なるらしい
     if x.len() > 1 {
         let mid_point = x.len() / 2;
-        sort(&mut x[..mid_point], true);
-        sort(&mut x[mid_point..], false);
+        do_sort(&mut x[..mid_point], true);
+        do_sort(&mut x[mid_point..], false);
         sub_sort(x, up);
     }
 }
@@ -29,15 +41,39 @@ fn compare_and_swap(x: &mut[u32], up: bool) {
     }
 }
 
+/// `Sorter` トレイトで first を呼び出すための型
+/// 
+/// first は u32 専用で、長さが 2 の冪乗のスライスしか正しくソートできない
+pub struct First;
+
+impl Sorter<u32> for First {
+    fn name(&self) -> &'static str {
+        "first"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { parallel: false, generic: false, power_of_two_only: true, comparator: false }
+    }
+
+    fn sort(&self, x: &mut [u32], order: &SortOrder) -> Result<(), SortError> {
+        sort(x, matches!(*order, SortOrder::Ascending))
+    }
+
+    fn sort_by(&self, _x: &mut [u32], _comparator: &(dyn Fn(&u32, &u32) -> Ordering + Sync)) -> Result<(), SortError> {
+        Err(SortError::Unsupported { sorter: self.name() })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::sort;
+    use crate::SortError;
 
     #[test]
     fn sort_u32_ascending() {
         let mut x = vec![10, 30, 11, 20, 4, 330, 21, 110];
 
-        sort(&mut x, true);
+        assert_eq!(sort(&mut x, true), Ok(()));
 
         assert_eq!(x, vec![4, 10, 11, 20, 21, 30, 110, 330]);
     }
@@ -46,8 +82,15 @@ mod tests {
     fn sort_u32_descending() {
         let mut x = vec![10, 30, 11, 20, 4, 330, 21, 110];
 
-        sort(&mut x, false);
+        assert_eq!(sort(&mut x, false), Ok(()));
 
         assert_eq!(x, vec![330, 110, 30, 21, 20, 11, 10, 4]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn sort_invalid_length() {
+        let mut x = vec![10, 30, 11];
+
+        assert_eq!(sort(&mut x, true), Err(SortError::InvalidLength { len: 3 }));
+    }
+}
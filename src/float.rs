@@ -0,0 +1,97 @@
+use super::{NanPolicy, SortError, SortOrder};
+use std::cmp::Ordering;
+
+/// ソートできる浮動小数点数 (f32, f64)
+pub trait Float: Copy + PartialOrd + Send + Sync {
+    fn is_nan(self) -> bool;
+
+    /// IEEE 754 の totalOrder による比較
+    fn total_cmp(&self, other: &Self) -> Ordering;
+}
+
+impl Float for f32 {
+    fn is_nan(self) -> bool {
+        f32::is_nan(self)
+    }
+
+    fn total_cmp(&self, other: &Self) -> Ordering {
+        f32::total_cmp(self, other)
+    }
+}
+
+impl Float for f64 {
+    fn is_nan(self) -> bool {
+        f64::is_nan(self)
+    }
+
+    fn total_cmp(&self, other: &Self) -> Ordering {
+        f64::total_cmp(self, other)
+    }
+}
+
+/// order と policy に従って 2 つの浮動小数点数を比較する comparator を作る
+/// 
+/// README の step 3 で書いた「NaN を最も大きい値として扱うクロージャ」を、NaN の扱いごとに用意したもの。
+/// `NanPolicy::Error` の場合は、呼び出し側で事前に `check_nan` しておくこと (NaN は末尾に置く扱いになる)
+pub fn comparator<T: Float>(order: &SortOrder, policy: NanPolicy) -> impl Fn(&T, &T) -> Ordering + Sync + Copy {
+    let ascending = matches!(*order, SortOrder::Ascending);
+    let nan_first = policy == NanPolicy::NanFirst;
+
+    move |a: &T, b: &T| {
+        let oriented = |ordering: Ordering| if ascending { ordering } else { ordering.reverse() };
+
+        if policy == NanPolicy::TotalOrder {
+            return oriented(a.total_cmp(b));
+        }
+        // NaN の位置はソート順序によらないので、oriented を通さない
+        match (a.is_nan(), b.is_nan()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => if nan_first { Ordering::Less } else { Ordering::Greater },
+            (false, true) => if nan_first { Ordering::Greater } else { Ordering::Less },
+            (false, false) => oriented(a.partial_cmp(b).unwrap()),
+        }
+    }
+}
+
+/// policy が `NanPolicy::Error` のとき、最初の NaN の位置をエラーとして返す
+pub fn check_nan<T, K, F>(x: &[T], key: &F, policy: NanPolicy) -> Result<(), SortError>
+    where K: Float,
+        F: Fn(&T) -> K,
+{
+    if policy != NanPolicy::Error {
+        return Ok(());
+    }
+    match x.iter().position(|v| key(v).is_nan()) {
+        Some(index) => Err(SortError::NaN { index }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::comparator;
+    use crate::NanPolicy;
+    use crate::SortOrder::{Ascending as Asc, Descending as Desc};
+    use std::cmp::Ordering;
+
+    #[test]
+    fn compare_with_nan() {
+        let nan_last = comparator::<f64>(&Asc, NanPolicy::NanLast);
+        assert_eq!(nan_last(&f64::NAN, &1.0), Ordering::Greater);
+        assert_eq!(nan_last(&f64::NAN, &f64::NAN), Ordering::Equal);
+        assert_eq!(nan_last(&-0.0, &0.0), Ordering::Equal);
+
+        // 降順でも NaN は末尾
+        let nan_last_desc = comparator::<f64>(&Desc, NanPolicy::NanLast);
+        assert_eq!(nan_last_desc(&f64::NAN, &1.0), Ordering::Greater);
+        assert_eq!(nan_last_desc(&2.0, &1.0), Ordering::Less);
+
+        let nan_first = comparator::<f32>(&Asc, NanPolicy::NanFirst);
+        assert_eq!(nan_first(&f32::NAN, &f32::NEG_INFINITY), Ordering::Less);
+
+        let total = comparator::<f64>(&Asc, NanPolicy::TotalOrder);
+        assert_eq!(total(&-0.0, &0.0), Ordering::Less);
+        assert_eq!(total(&f64::NAN, &f64::INFINITY), Ordering::Greater);
+        assert_eq!(total(&-f64::NAN, &f64::NEG_INFINITY), Ordering::Less);
+    }
+}
@@ -1,9 +1,87 @@
-use super::SortOrder;
+use super::{NanPolicy, SortError, SortOrder};
+use crate::float::{self, Float};
+use crate::leaf::{self, LeafConfig};
+use crate::network::Network;
+use crate::sorter::{Capabilities, Sorter};
+use crate::storage::{ByComparator, SortStorage, SplitStorage, WithValues};
+use crate::third;
+use crate::utils::{apply_permutation, catch_comparator_panic, greatest_power_of_two_below};
+use rayon::prelude::*;
+use rayon::ThreadPool;
 use std::cmp::Ordering;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
 
-const PARALLEL_THRESHOLD: usize = 4096;
+/// ParallelConfig::default() で使う並列化のしきい値
+pub const PARALLEL_THRESHOLD: usize = 4096;
 
-pub fn sort<T: Ord + Send>(x: &mut [T], order: &SortOrder) -> Result<(), String> {
+/// fourth の並列ソートの設定
+/// 
+/// 1 つのプロセスで複数のソート処理を動かすときに、それぞれが使う CPU を制限できるようにする
+#[derive(Debug, Clone)]
+pub struct ParallelConfig {
+    /// 分割後の要素数がこの値以上のときだけ rayon::join で並列実行する
+    pub threshold: usize,
+    /// rayon::join で並列に分割する再帰の深さの上限。これより深い部分は逐次実行する
+    pub max_depth: usize,
+    /// ソートを実行するスレッドプール。None の場合は rayon のグローバルプールを使う
+    pub pool: Option<Arc<ThreadPool>>,
+    /// 再帰を打ち切る leaf の大きさとソート方法
+    pub leaf: LeafConfig,
+    /// ソートを途中で止めるためのトークン。None の場合はキャンセルできない
+    pub cancel: Option<CancelToken>,
+}
+
+/// 実行中の sort_by_with_config などを、別のスレッドから止めるためのトークン
+/// 
+/// clone したトークンは同じフラグを共有する。cancel されると再帰の各段が新しい比較を始めずに戻り、
+/// ソート関数は `SortError::Cancelled` を返す
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, AtomicOrdering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(AtomicOrdering::Relaxed)
+    }
+}
+
+impl Default for ParallelConfig {
+    fn default() -> Self {
+        Self {
+            threshold: PARALLEL_THRESHOLD,
+            max_depth: usize::MAX,
+            pool: None,
+            leaf: LeafConfig::default(),
+            cancel: None,
+        }
+    }
+}
+
+impl ParallelConfig {
+    /// この深さ・要素数で並列実行するかどうか
+    ///
+    /// len は分割した片側の要素数。0 で分割しても同じ長さの再帰が続くだけなので、threshold が 0 でも分割しない
+    fn should_split(&self, len: usize, depth: usize) -> bool {
+        len > 0 && len >= self.threshold && depth < self.max_depth
+    }
+
+    fn is_cancelled(&self) -> bool {
+        match self.cancel {
+            Some(ref token) => token.is_cancelled(),
+            None => false,
+        }
+    }
+}
+
+pub fn sort<T: Ord + Send>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
     match *order {
         SortOrder::Ascending  => sort_by(x, &|a, b| a.cmp(b)),
         SortOrder::Descending => sort_by(x, &|a, b| b.cmp(a)),
@@ -18,103 +96,516 @@ pub fn sort<T: Ord + Send>(x: &mut [T], order: &SortOrder) -> Result<(), String>
 //
 // なので、comparator のところに具体的な型は書けない。
 // -> クロージャを引数にとる場合はジェネリクスにする必要がある
-pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), String>
+pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
     where T: Send,
         F: Sync + Fn(&T, &T) -> Ordering,
 {
-    if x.len().is_power_of_two() {
-        do_sort(x, true, comparator);
-        Ok(())
-    } else {
-        Err(format!("The length of x is not a power of two. x.len(): {}", x.len()))
+    sort_by_with_config(x, comparator, &ParallelConfig::default())
+}
+
+/// 並列化の設定を指定してソートする
+pub fn sort_by_with_config<T, F>(x: &mut [T], comparator: &F, config: &ParallelConfig) -> Result<(), SortError>
+    where T: Send,
+        F: Sync + Fn(&T, &T) -> Ordering,
+{
+    // third と同じく任意長の bitonic network を使うので、長さの制約はない
+    run(config, || do_sort(&mut ByComparator::new(x, comparator), true, config, 0))
+}
+
+/// config で sort_by_with_config が実行するソーティングネットワークを取り出す
+/// 
+/// fourth は third と同じネットワークを、互いに依存しない部分を rayon で並列に実行する。
+/// 並列化の設定 (threshold, max_depth, pool) は実行の順序だけを変えるので、ネットワークは config.leaf だけで決まる。
+/// leaf が挿入ソートの場合は入力によって比較が変わるので `SortError::Unsupported` を返す
+pub fn network(n: usize, config: &ParallelConfig) -> Result<Network, SortError> {
+    third::network(n, &config.leaf).map_err(|_| SortError::Unsupported { sorter: "fourth" })
+}
+
+/// config.pool があればそのプールで f を実行する
+/// 
+/// f の中で comparator が panic した場合は `SortError::ComparatorPanic` を、
+/// config.cancel でキャンセルされた場合は `SortError::Cancelled` を返す。
+/// ソートが終わった直後にキャンセルされた場合も Cancelled になることがある
+fn run<F: Send + FnOnce()>(config: &ParallelConfig, f: F) -> Result<(), SortError> {
+    catch_comparator_panic(|| match config.pool {
+        // install の中で呼んだ rayon::join は、そのプールのスレッドで実行される
+        // (別スレッドでの panic も join を通して呼び出し元に伝わる)
+        Some(ref pool) => pool.install(f),
+        None => f(),
+    })?;
+    if config.is_cancelled() {
+        return Err(SortError::Cancelled);
+    }
+    Ok(())
+}
+
+/// PartialOrd だけを実装した型をソートする
+/// 
+/// `partial_cmp` が `None` を返す (比較できない) 組が見つかった場合は、x を変更せずに
+/// その組のインデックス (元の x での位置) を `SortError::Incomparable` で返す。
+/// 返すのは、ソートの途中で実際に比較した組のうち (left, right) が辞書順で最小のもの。
+/// どの組を比較するかは要素の並び (データ) によって変わるので、x 全体で辞書順最小の比較できない組とは限らない。
+/// 比較は並列に行われるが、どの組を比較するかはデータだけで決まるので、結果はスレッドの実行順によらず
+/// third::sort_partial とも一致する
+pub fn sort_partial<T>(x: &mut [T], order: &SortOrder) -> Result<(), SortError>
+    where T: PartialOrd + Send + Sync,
+{
+    let ascending = matches!(*order, SortOrder::Ascending);
+    let incomparable: Mutex<Option<(usize, usize)>> = Mutex::new(None);
+
+    // 要素を動かす前に比較できない組を見つけたいので、インデックスの列をソートしてから並べ替える
+    let mut indices: Vec<usize> = (0..x.len()).collect();
+    {
+        let x: &[T] = x;
+        sort_by(&mut indices, &|&a: &usize, &b: &usize| {
+            let ordering = match x[a].partial_cmp(&x[b]) {
+                Some(ordering) => if ascending { ordering } else { ordering.reverse() },
+                None => {
+                    let pair = (a.min(b), a.max(b));
+                    let mut found = incomparable.lock().unwrap();
+                    match *found {
+                        Some(f) if f <= pair => {},
+                        _ => *found = Some(pair),
+                    }
+                    Ordering::Equal
+                },
+            };
+            ordering.then(a.cmp(&b))
+        })?;
+    }
+
+    if let Some((left, right)) = incomparable.into_inner().unwrap() {
+        return Err(SortError::Incomparable { left, right });
+    }
+    apply_permutation(x, &indices);
+    Ok(())
+}
+
+/// f32, f64 のスライスを NaN の扱い (policy) を指定してソートする
+/// 
+/// policy が `NanPolicy::Error` で NaN が含まれている場合は、x を変更せずに最初の NaN の位置を返す
+pub fn sort_floats<T: Float>(x: &mut [T], order: &SortOrder, policy: NanPolicy) -> Result<(), SortError> {
+    sort_floats_by(x, &|v: &T| *v, order, policy)
+}
+
+/// key で取り出した浮動小数点数の順にソートする。NaN の扱いは sort_floats と同じ
+pub fn sort_floats_by<T, K, F>(x: &mut [T], key: &F, order: &SortOrder, policy: NanPolicy) -> Result<(), SortError>
+    where T: Send,
+        K: Float,
+        F: Sync + Fn(&T) -> K,
+{
+    float::check_nan(x, key, policy)?;
+    let comparator = float::comparator(order, policy);
+    sort_by(x, &|a, b| comparator(&key(a), &key(b)))
+}
+
+/// key で取り出した値の昇順にソートする。key は比較のたびに呼ばれる
+pub fn sort_by_key<T, K, F>(x: &mut [T], key: &F) -> Result<(), SortError>
+    where T: Send,
+        K: Ord,
+        F: Sync + Fn(&T) -> K,
+{
+    sort_by(x, &|a, b| key(a).cmp(&key(b)))
+}
+
+/// key で取り出した値の昇順にソートする。key は各要素について 1 回だけ呼ばれる
+/// 
+/// key の計算が重い場合 (文字列のパースや小文字化など) 向け。(key, 元のインデックス) の組を先に計算して
+/// bitonic network でソートし、その順序で x を並べ替える。組にインデックスが入っているので、結果は安定ソートになる。
+/// key の計算も rayon で並列に行う
+pub fn sort_by_cached_key<T, K, F>(x: &mut [T], key: &F) -> Result<(), SortError>
+    where T: Send + Sync,
+        K: Ord + Send,
+        F: Sync + Fn(&T) -> K,
+{
+    let mut keys: Vec<(K, usize)> = x.par_iter()
+        .map(key)
+        .enumerate()
+        .map(|(i, k)| (k, i))
+        .collect();
+    sort_by(&mut keys, &|a, b| a.cmp(b))?;
+
+    let perm: Vec<usize> = keys.into_iter().map(|(_, i)| i).collect();
+    apply_permutation(x, &perm);
+    Ok(())
+}
+
+/// keys と values を組として、keys の順序でソートする
+/// 
+/// keys で行った swap をすべて values にも同じ位置で行う。長さが違う場合は `SortError::LengthMismatch` を返す
+pub fn sort_pairs<K, V>(keys: &mut [K], values: &mut [V], order: &SortOrder) -> Result<(), SortError>
+    where K: Ord + Send,
+        V: Send,
+{
+    match *order {
+        SortOrder::Ascending  => sort_pairs_by(keys, values, &|a, b| a.cmp(b)),
+        SortOrder::Descending => sort_pairs_by(keys, values, &|a, b| b.cmp(a)),
+    }
+}
+
+pub fn sort_pairs_by<K, V, F>(keys: &mut [K], values: &mut [V], comparator: &F) -> Result<(), SortError>
+    where K: Send,
+        V: Send,
+        F: Sync + Fn(&K, &K) -> Ordering,
+{
+    sort_pairs_by_with_config(keys, values, comparator, &ParallelConfig::default())
+}
+
+/// 並列化の設定を指定して sort_pairs_by を実行する
+pub fn sort_pairs_by_with_config<K, V, F>(keys: &mut [K], values: &mut [V], comparator: &F, config: &ParallelConfig)
+    -> Result<(), SortError>
+    where K: Send,
+        V: Send,
+        F: Sync + Fn(&K, &K) -> Ordering,
+{
+    if keys.len() != values.len() {
+        return Err(SortError::LengthMismatch { keys: keys.len(), values: values.len() });
     }
+    let mut storage = WithValues::new(ByComparator::new(keys, comparator), values);
+    run(config, || do_sort(&mut storage, true, config, 0))
 }
 
-fn do_sort<T, F>(x: &mut [T], forward: bool, comparator: &F)
+/// x をソートしたときの並び順を、インデックスの列で返す。x 自体は変更しない
+/// 
+/// 戻り値を perm とすると、x[perm[0]], x[perm[1]], ... がソート済みの順序になる。
+/// 複数の列 (カラム) を同じ順序で並べ替えたいときは、`utils::apply_permutation` でそれぞれに適用する
+pub fn argsort<T>(x: &[T], order: &SortOrder) -> Result<Vec<usize>, SortError>
+    where T: Ord + Sync,
+{
+    match *order {
+        SortOrder::Ascending  => argsort_by(x, &|a, b| a.cmp(b)),
+        SortOrder::Descending => argsort_by(x, &|a, b| b.cmp(a)),
+    }
+}
+
+/// comparator の順序での argsort。comparator で等しい要素は元のインデックス順に並ぶ (安定)
+pub fn argsort_by<T, F>(x: &[T], comparator: &F) -> Result<Vec<usize>, SortError>
+    where T: Sync,
+        F: Sync + Fn(&T, &T) -> Ordering
+{
+    // 等しい要素は元のインデックスで比較するので、インデックスの列に対しては全順序になる
+    let mut indices: Vec<usize> = (0..x.len()).collect();
+    sort_by(&mut indices, &|&a: &usize, &b: &usize| comparator(&x[a], &x[b]).then(a.cmp(&b)))?;
+    Ok(indices)
+}
+
+/// 安定ソート。comparator で等しいと判定された要素は、元の順序を保つ
+/// 
+/// bitonic sort は離れた要素同士を交換するので安定ではない。そこで要素そのものではなく
+/// インデックスの列を argsort_by でソートし、最後にその置換で x を並べ替える。要素をコピーしないので T: Clone は不要。
+/// インデックスのソートは並列に行うので、comparator から x を共有できるように T: Sync が必要
+pub fn stable_sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+    where T: Send + Sync,
+        F: Sync + Fn(&T, &T) -> Ordering
+{
+    let indices = argsort_by(x, comparator)?;
+    apply_permutation(x, &indices);
+    Ok(())
+}
+
+/// 前半 x[..mid] と後半 x[mid..] がそれぞれ order の順にソート済みのとき、x 全体をその順にマージする
+/// 
+/// third::bitonic_merge の並列版。mid > x.len() の場合は `SortError::InvalidLength` を返す
+pub fn bitonic_merge<T: Ord + Send>(x: &mut [T], mid: usize, order: &SortOrder) -> Result<(), SortError> {
+    match *order {
+        SortOrder::Ascending  => bitonic_merge_by(x, mid, &|a, b| a.cmp(b)),
+        SortOrder::Descending => bitonic_merge_by(x, mid, &|a, b| b.cmp(a)),
+    }
+}
+
+pub fn bitonic_merge_by<T, F>(x: &mut [T], mid: usize, comparator: &F) -> Result<(), SortError>
     where T: Send,
         F: Sync + Fn(&T, &T) -> Ordering,
 {
-    if x.len() > 1 {
-        let mid_point = x.len() / 2;
+    if mid > x.len() {
+        return Err(SortError::InvalidLength { len: x.len() });
+    }
+    merge(x, mid, comparator, &ParallelConfig::default());
+    Ok(())
+}
+
+/// x を先頭から runs の長さずつ区切った区間 (ラン) がそれぞれ order の順にソート済みのとき、x 全体をマージする
+/// 
+/// third::merge_runs の並列版。同じ段で行うマージ同士は重ならないので、それぞれを並列に実行する。
+/// runs の合計が x.len() と違う場合は `SortError::InvalidLength` を返す
+pub fn merge_runs<T: Ord + Send>(x: &mut [T], runs: &[usize], order: &SortOrder) -> Result<(), SortError> {
+    match *order {
+        SortOrder::Ascending  => merge_runs_by(x, runs, &|a, b| a.cmp(b)),
+        SortOrder::Descending => merge_runs_by(x, runs, &|a, b| b.cmp(a)),
+    }
+}
+
+pub fn merge_runs_by<T, F>(x: &mut [T], runs: &[usize], comparator: &F) -> Result<(), SortError>
+    where T: Send,
+        F: Sync + Fn(&T, &T) -> Ordering,
+{
+    if runs.iter().sum::<usize>() != x.len() {
+        return Err(SortError::InvalidLength { len: x.len() });
+    }
+
+    let config = ParallelConfig::default();
+    let mut runs = runs.to_vec();
+    while runs.len() > 1 {
+        let mut merged = Vec::with_capacity(runs.len().div_ceil(2));
+        // 隣り合う 2 つのランをまとめた区間を split_at_mut で切り出しておく
+        let mut blocks = Vec::with_capacity(runs.len() / 2);
+        let mut rest = &mut x[..];
+        for pair in runs.chunks(2) {
+            let len = pair.iter().sum::<usize>();
+            let (block, tail) = std::mem::take(&mut rest).split_at_mut(len);
+            rest = tail;
+            if let [first, _] = *pair {
+                blocks.push((block, first));
+            }
+            merged.push(len);
+        }
+        blocks.into_par_iter()
+            .for_each(|(block, mid)| merge(block, mid, comparator, &config));
+        runs = merged;
+    }
+    Ok(())
+}
+
+/// 前半を反転して x 全体を bitonic 列にしてから、マージ段 (sub_sort) を実行する
+fn merge<T, F>(x: &mut [T], mid: usize, comparator: &F, config: &ParallelConfig)
+    where T: Send,
+        F: Sync + Fn(&T, &T) -> Ordering,
+{
+    // 前半の反転も、両端から組にして並列に入れ替える。奇数個のときは真ん中の要素が残る
+    let (left, right) = x[..mid].split_at_mut(mid / 2);
+    left.par_iter_mut()
+        .zip(right.par_iter_mut().rev())
+        .with_min_len(config.threshold)
+        .for_each(|(a, b)| std::mem::swap(a, b));
+
+    sub_sort(&mut ByComparator::new(x, comparator), true, config, 0);
+}
+
+/// x のうち order の順で先頭に来る k 要素だけを、ソート済みの状態で x[..k] に集める。x[k..] の順序は不定
+/// 
+/// third::partial_sort の並列版。x を前後に分けてそれぞれの先頭 k 要素を並列に求め、
+/// 2 つの候補をマージして大きい方を捨てる。k が x.len() 以上の場合は x 全体をソートする
+pub fn partial_sort<T: Ord + Send>(x: &mut [T], k: usize, order: &SortOrder) -> Result<(), SortError> {
+    match *order {
+        SortOrder::Ascending  => partial_sort_by(x, k, &|a, b| a.cmp(b)),
+        SortOrder::Descending => partial_sort_by(x, k, &|a, b| b.cmp(a)),
+    }
+}
+
+pub fn partial_sort_by<T, F>(x: &mut [T], k: usize, comparator: &F) -> Result<(), SortError>
+    where T: Send,
+        F: Sync + Fn(&T, &T) -> Ordering,
+{
+    let k = k.min(x.len());
+    if k > 0 {
+        par_top_k_in_place(x, k, comparator, &ParallelConfig::default(), 0);
+        x[..k].reverse();
+    }
+    Ok(())
+}
+
+/// 並列イテレータの要素のうち comparator の順で先頭に来る k 要素を、ソート済みの Vec で返す
+/// 
+/// 各スレッドが fold で自分の候補バッファ (2k 要素になったら k 要素に絞る) を作り、reduce で候補同士をマージする
+pub fn top_k<I, F>(iter: I, k: usize, comparator: &F) -> Result<Vec<I::Item>, SortError>
+    where I: IntoParallelIterator,
+        F: Sync + Fn(&I::Item, &I::Item) -> Ordering,
+{
+    if k == 0 {
+        return Ok(Vec::new());
+    }
+
+    // 候補を先頭 k 要素 (comparator の逆順) に絞る
+    let shrink = |mut buffer: Vec<I::Item>| {
+        if buffer.len() > k {
+            third::top_k_in_place(&mut buffer, k, comparator);
+            buffer.truncate(k);
+        }
+        buffer
+    };
+
+    let mut buffer = iter.into_par_iter()
+        .fold(Vec::new, |mut buffer, item| {
+            buffer.push(item);
+            if buffer.len() == 2 * k {
+                buffer = shrink(buffer);
+            }
+            buffer
+        })
+        .reduce(Vec::new, |mut a, mut b| {
+            a.append(&mut b);
+            shrink(a)
+        });
+
+    let n = buffer.len().min(k);
+    if n > 0 {
+        third::top_k_in_place(&mut buffer, n, comparator);
+        buffer.truncate(n);
+    }
+    buffer.reverse();
+    Ok(buffer)
+}
+
+/// x のうち comparator の順で先頭に来る k 要素 (0 < k <= x.len()) を、comparator の逆順で x[..k] に集める
+fn par_top_k_in_place<T, F>(x: &mut [T], k: usize, comparator: &F, config: &ParallelConfig, depth: usize)
+    where T: Send,
+        F: Sync + Fn(&T, &T) -> Ordering,
+{
+    // 前半・後半のどちらにも k 要素以上残るように分ける
+    let mid_point = x.len() / 2;
+    if mid_point < k || !config.should_split(mid_point, depth) {
+        third::top_k_in_place(x, k, comparator);
+        return;
+    }
+
+    let (first, second) = x.split_at_mut(mid_point);
+    rayon::join(
+        || par_top_k_in_place(first, k, comparator, config, depth + 1),
+        || par_top_k_in_place(second, k, comparator, config, depth + 1)
+    );
+    // 後半の候補を comparator の順に直して、前半の候補とマージする
+    second[..k].reverse();
+    third::merge_top_k(&mut first[..k], &mut second[..k], comparator);
+}
+
+fn do_sort<S>(s: &mut S, forward: bool, config: &ParallelConfig, depth: usize)
+    where S: SplitStorage + Send
+{
+    let n = s.len();
+    if config.is_cancelled() {
+        return;
+    }
+    if n > 1 && n <= config.leaf.cutoff() {
+        // 十分小さいブロックは再帰せずにまとめてソートする
+        leaf::sort_leaf(s, 0, n, config.leaf.sort, forward);
+    } else if n > 1 {
+        let mid_point = n / 2;
 
         // x の可変参照を2つ以上作らせない実装（NG例）
         // let first = &mut x[..mid_point];  // first, second で事前分割しても、x の可変参照はこの時点で作られてしまうので、
         // let second  = &mut x[mid_point..];  // （承前）second のところで "cannot borrow as mutable" で弾かれる
 
-        // x の可変参照を2つ以上作らせない実装（OK例）
-        let (first, second) = x.split_at_mut(mid_point);  // mid_point を堺にした2つの可変参照に分割して、それぞれ first, second に束縛
+        {
+            // x の可変参照を2つ以上作らせない実装（OK例）
+            // mid_point を堺にした2つの可変参照に分割して、それぞれ first, second に束縛 (スライスなら x.split_at_mut(mid_point))
+            let (mut first, mut second) = s.split_at_mut(mid_point);
 
-        if mid_point >= PARALLEL_THRESHOLD {
-            // 要素数がしきい値以上なら並列実行する。
-            // しきい値はスレッド作成のオーバーヘッドとの兼ね合い
-            rayon::join(
-                || do_sort(first, true, comparator),
-                || do_sort(second, false, comparator)
-            );
-            // rayon_core::join
-            // pub fn join<A, B, RA, RB>(oper_a: A, oper_b: B) -> (RA, RB)
-            // where
-            //     A: FnOnce<(), Output = RA> + Send,
-            //     B: FnOnce<(), Output = RB> + Send,
-            //     RA: Send,
-            //     RB: Send,
-            // A, B はクロージャの型で、それぞれの戻り値が RA, RB ということらしい。
-            // Send は謎。
-            // mutable で参照渡ししている x は大丈夫なのか？という疑問はあるが、
-            // 分割統治のアルゴリズムなので再帰の深い場所から上がってくるだけだし多重更新みたいな問題は起きないだろう...と、理解している
-        } else {
-            do_sort(first, true, comparator);
-            do_sort(second, false, comparator);
+            if config.should_split(mid_point, depth) {
+                // 要素数がしきい値以上なら並列実行する。
+                // しきい値はスレッド作成のオーバーヘッドとの兼ね合い
+                // 任意長に対応するため、前半を逆順・後半を正順でソートする (third と同じ)
+                rayon::join(
+                    || do_sort(&mut first, !forward, config, depth + 1),
+                    || do_sort(&mut second, forward, config, depth + 1)
+                );
+                // rayon_core::join
+                // pub fn join<A, B, RA, RB>(oper_a: A, oper_b: B) -> (RA, RB)
+                // where
+                //     A: FnOnce<(), Output = RA> + Send,
+                //     B: FnOnce<(), Output = RB> + Send,
+                //     RA: Send,
+                //     RB: Send,
+                // A, B はクロージャの型で、それぞれの戻り値が RA, RB ということらしい。
+                // Send は謎。
+                // mutable で参照渡ししている x は大丈夫なのか？という疑問はあるが、
+                // 分割統治のアルゴリズムなので再帰の深い場所から上がってくるだけだし多重更新みたいな問題は起きないだろう...と、理解している
+            } else {
+                do_sort(&mut first, !forward, config, depth);
+                do_sort(&mut second, forward, config, depth);
+            }
         }
-        sub_sort(x, forward, comparator);
+        sub_sort(s, forward, config, depth);
     }
 }
 
-fn sub_sort<T, F>(x: &mut [T], forward: bool, comparator: &F)
-    where T: Send,
-        F: Sync + Fn(&T, &T) -> Ordering
+fn sub_sort<S>(s: &mut S, forward: bool, config: &ParallelConfig, depth: usize)
+    where S: SplitStorage + Send
 {
-    if x.len() > 1 {
-        compare_and_swap(x, forward, comparator);
-        let mid_point = x.len() / 2;
-        let (first, second) = x.split_at_mut(mid_point);
-        if mid_point >= PARALLEL_THRESHOLD {
+    let n = s.len();
+    if n > 1 && !config.is_cancelled() {
+        let mid_point = greatest_power_of_two_below(n);
+        let (mut first, mut second) = s.split_at_mut(mid_point);
+        // 比較相手が存在するのは前半の先頭 second.len() 個だけ
+        let pairs = second.len();
+        compare_and_swap(&mut first, &mut second, pairs, forward, config, depth);
+        // 後半は mid_point より短いことがあるので、並列化の判定は長い方 (前半) で行う
+        if config.should_split(mid_point, depth) {
             rayon::join(
-                || sub_sort(first, forward, comparator),
-                || sub_sort(second, forward, comparator)
+                || sub_sort(&mut first, forward, config, depth + 1),
+                || sub_sort(&mut second, forward, config, depth + 1)
             );
         } else {
-            sub_sort(first, forward, comparator);
-            sub_sort(second, forward, comparator);
+            sub_sort(&mut first, forward, config, depth);
+            sub_sort(&mut second, forward, config, depth);
         }
     }
 }
 
-fn compare_and_swap<T, F>(x: &mut[T], forward: bool, comparator: &F)
-    where F: Fn(&T, &T) -> Ordering
+/// i < pairs について first の i 番目と second の i 番目を比較して、順序が逆なら入れ替える
+/// 
+/// 最上位のマージ段では first, second がそれぞれ n/2 要素になり、ここが逐次だと O(n) の処理が
+/// 1 スレッドに偏る。要素数がしきい値以上のときは first, second を同じ位置で split_at_mut して、
+/// 前半同士・後半同士の組を rayon::join で並列に処理する (組同士は重ならないので安全に分割できる)
+fn compare_and_swap<S>(first: &mut S, second: &mut S, pairs: usize, forward: bool,
+    config: &ParallelConfig, depth: usize)
+    where S: SplitStorage + Send
 {
+    let mid_point = pairs / 2;
+    if config.should_split(mid_point, depth) {
+        let (mut first_l, mut first_r) = first.split_at_mut(mid_point);
+        let (mut second_l, mut second_r) = second.split_at_mut(mid_point);
+        rayon::join(
+            || compare_and_swap(&mut first_l, &mut second_l, mid_point, forward, config, depth + 1),
+            || compare_and_swap(&mut first_r, &mut second_r, pairs - mid_point, forward, config, depth + 1)
+        );
+        return;
+    }
+
     let swap_condition = if forward {
         Ordering::Greater
     } else {
         Ordering::Less
     };
 
-    let mid_point = x.len() / 2;
-
-    for i in 0..mid_point {
-        if comparator(&x[i], &x[mid_point + i]) == swap_condition {
-            // x[i] = x[mid_point+i];
-            // x[mid_point] = x[i];
-            x.swap(i, mid_point+i);
+    for i in 0..pairs {
+        if first.compare_with(i, second, i) == swap_condition {
+            first.swap_with(i, second, i);
         }
     }
 }
 
+/// `Sorter` トレイトで fourth を呼び出すための型
+pub struct Fourth;
+
+impl<T: Ord + Send> Sorter<T> for Fourth {
+    fn name(&self) -> &'static str {
+        "fourth"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { parallel: true, generic: true, power_of_two_only: false, comparator: true }
+    }
+
+    fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+        sort(x, order)
+    }
+
+    fn sort_by(&self, x: &mut [T], comparator: &(dyn Fn(&T, &T) -> Ordering + Sync)) -> Result<(), SortError> {
+        sort_by(x, &|a: &T, b: &T| comparator(a, b))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{sort, sort_by};
+    use rayon::prelude::*;
+    use super::{argsort, argsort_by, sort, sort_by, sort_pairs, sort_partial, sort_floats, sort_floats_by, sort_by_key, sort_by_cached_key, stable_sort_by, sort_by_with_config, sort_pairs_by_with_config, network, CancelToken, ParallelConfig, bitonic_merge, merge_runs, partial_sort, top_k};
+    use crate::leaf::{LeafConfig, LeafSort};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+    use crate::{third, NanPolicy, SortError};
     use crate::SortOrder::{Ascending as Asc, Descending as Desc};
     use crate::utils::{
+        apply_permutation,
         new_u32_vec,
         // is_sorted_ascending,
         // is_sorted_descending,
@@ -125,7 +616,7 @@ mod tests {
     fn sort_u32_ascending() {
         let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
 
-        sort(&mut x, &Asc);
+        assert_eq!(sort(&mut x, &Asc), Ok(()));
 
         assert_eq!(x, vec![4, 10, 11, 20, 21, 30, 110, 330]);
     }
@@ -134,7 +625,7 @@ mod tests {
     fn sort_u32_descending() {
         let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
 
-        sort(&mut x, &Desc);
+        assert_eq!(sort(&mut x, &Desc), Ok(()));
 
         assert_eq!(x, vec![330, 110, 30, 21, 20, 11, 10, 4]);
     }
@@ -143,7 +634,7 @@ mod tests {
     fn sort_str_ascending() {
         let mut x = vec!["Rust", "is", "fast", "and", "memory-efficient", "with", "no", "GC"];
 
-        sort(&mut x, &Asc);
+        assert_eq!(sort(&mut x, &Asc), Ok(()));
 
         assert_eq!(x, vec!["GC", "Rust", "and", "fast", "is", "memory-efficient", "no", "with"]);
     }
@@ -152,7 +643,7 @@ mod tests {
     fn sort_str_descending() {
         let mut x = vec!["Rust", "is", "fast", "and", "memory-efficient", "with", "no", "GC"];
 
-        sort(&mut x, &Desc);
+        assert_eq!(sort(&mut x, &Desc), Ok(()));
 
         assert_eq!(x, vec!["with", "no", "memory-efficient", "is", "fast", "and","Rust", "GC"]);
     }
@@ -260,4 +751,525 @@ mod tests {
             assert!(is_sorted(&x, &Desc));
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn sort_u32_arbitrary_length() {
+        // 2 の冪乗でない長さ (0, 1 を含む) でもソートできること
+        for &len in &[0, 1, 2, 3, 5, 7, 12, 100, 1000, 5000, 10007] {
+            let mut x = new_u32_vec(len);
+            let mut expected = x.clone();
+            expected.sort();
+
+            assert_eq!(sort(&mut x, &Asc), Ok(()));
+            assert_eq!(x, expected);
+
+            expected.reverse();
+            assert_eq!(sort(&mut x, &Desc), Ok(()));
+            assert_eq!(x, expected);
+        }
+    }
+
+    #[test]
+    fn sort_u32_with_config() {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let configs = vec![
+            ParallelConfig { threshold: 64, ..Default::default() },
+            ParallelConfig { threshold: 64, max_depth: 0, ..Default::default() },
+            ParallelConfig { threshold: 64, max_depth: 3, pool: Some(Arc::new(pool)), ..Default::default() },
+            ParallelConfig { threshold: 64, leaf: LeafConfig::disabled(), ..Default::default() },
+            ParallelConfig { threshold: 64, leaf: LeafConfig { size: 100, sort: LeafSort::Insertion }, ..Default::default() },
+        ];
+
+        for config in &configs {
+            let mut x = new_u32_vec(10000);
+            let mut expected = x.clone();
+            expected.sort();
+
+            assert_eq!(sort_by_with_config(&mut x, &|a, b| a.cmp(b), config), Ok(()));
+            assert_eq!(x, expected);
+        }
+    }
+
+    #[test]
+    fn sort_u32_with_parallel_compare_and_swap() {
+        // しきい値を小さくして、compare_and_swap の分割が奇数長・端数のある組でも正しく動くこと
+        for &len in &[3, 17, 1000, 4097] {
+            for &threshold in &[1, 2, 7] {
+                let config = ParallelConfig { threshold, ..Default::default() };
+                let mut x = new_u32_vec(len);
+                let mut expected = x.clone();
+                expected.sort();
+
+                assert_eq!(sort_by_with_config(&mut x, &|a, b| a.cmp(b), &config), Ok(()));
+                assert_eq!(x, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn same_comparators_as_network() {
+        // キーが重複していると、比較器の並びが違えば同じキーの要素の並び順が変わる
+        let configs = vec![
+            ParallelConfig { threshold: 1, ..Default::default() },
+            ParallelConfig { threshold: 3, leaf: LeafConfig::disabled(), ..Default::default() },
+            ParallelConfig { threshold: 5, leaf: LeafConfig { size: 5, sort: LeafSort::Network }, ..Default::default() },
+        ];
+        for config in &configs {
+            for &n in &[0, 1, 13, 100, 1000] {
+                let input: Vec<(u32, usize)> = new_u32_vec(n).into_iter().map(|v| v % 8).zip(0..).collect();
+                let comparator = |a: &(u32, usize), b: &(u32, usize)| a.0.cmp(&b.0);
+
+                let mut expected = input.clone();
+                assert_eq!(network(n, config).unwrap().apply(&mut expected, &comparator), Ok(()));
+
+                let mut x = input;
+                assert_eq!(sort_by_with_config(&mut x, &comparator, config), Ok(()));
+                assert_eq!(x, expected, "n = {}, config = {:?}", n, config);
+            }
+        }
+
+        let config = ParallelConfig { leaf: LeafConfig { size: 8, sort: LeafSort::Insertion }, ..Default::default() };
+        assert_eq!(network(13, &config), Err(SortError::Unsupported { sorter: "fourth" }));
+    }
+
+    #[test]
+    fn sort_u32_with_tiny_threshold() {
+        // threshold が 0 でも、1 要素の組を 0 要素で分割し続けないこと
+        for &threshold in &[0, 1] {
+            for &leaf in &[LeafConfig::default(), LeafConfig::disabled()] {
+                let config = ParallelConfig { threshold, leaf, ..Default::default() };
+                for &len in &[8, 13, 100] {
+                    let mut x: Vec<u32> = (0..len).rev().collect();
+                    assert_eq!(sort_by_with_config(&mut x, &|a, b| a.cmp(b), &config), Ok(()));
+                    assert_eq!(x, (0..len).collect::<Vec<u32>>(), "threshold = {}, leaf = {:?}", threshold, leaf);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sort_runs_on_given_pool() {
+        let pool = Arc::new(rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap());
+        let config = ParallelConfig { threshold: 1, pool: Some(pool.clone()), ..Default::default() };
+
+        // comparator がすべて指定したプールのスレッドで呼ばれていること
+        let mut x = new_u32_vec(256);
+        assert_eq!(
+            sort_by_with_config(&mut x, &|a, b| {
+                assert!(rayon::current_thread_index().is_some());
+                assert_eq!(rayon::current_num_threads(), 2);
+                a.cmp(b)
+            }, &config),
+            Ok(())
+        );
+        assert!(is_sorted(&x, &Asc));
+    }
+
+    #[test]
+    fn stable_sort_with_duplicate_keys() {
+        // (key, 入力順) の組を key だけで比較してソートし、同じ key の中で入力順が保たれていること
+        for &len in &[0, 1, 2, 10, 1000, 10000] {
+            let mut x: Vec<(u32, usize)> = new_u32_vec(len).into_iter()
+                .map(|v| v % 8)
+                .enumerate()
+                .map(|(i, key)| (key, i))
+                .collect();
+            let mut expected = x.clone();
+            expected.sort_by_key(|a| a.0);
+
+            assert_eq!(stable_sort_by(&mut x, &|a, b| a.0.cmp(&b.0)), Ok(()));
+            assert_eq!(x, expected);
+
+            // 降順でも同じ key の中では入力順
+            expected.sort_by_key(|a| std::cmp::Reverse(a.0));
+            let mut x = expected.clone();
+            x.sort_by_key(|&(_, i)| i);
+            assert_eq!(stable_sort_by(&mut x, &|a, b| b.0.cmp(&a.0)), Ok(()));
+            assert_eq!(x, expected);
+        }
+    }
+
+    #[test]
+    fn stable_sort_students_by_last_name() {
+        let taro = Student::new("Taro", "Yamada", 16);
+        let hanako = Student::new("Hanako", "Yamada", 14);
+        let kyoko = Student::new("Kyoko", "Ito", 15);
+        let ryosuke = Student::new("Ryosuke", "Hayashi", 17);
+
+        // Student は Clone を実装していないが、安定ソートできる
+        let mut x = vec![taro, hanako, kyoko, ryosuke];
+
+        assert_eq!(stable_sort_by(&mut x, &|a, b| a.last_name.cmp(&b.last_name)), Ok(()));
+
+        let names: Vec<&str> = x.iter().map(|s| s.first_name.as_str()).collect();
+        assert_eq!(names, vec!["Ryosuke", "Kyoko", "Taro", "Hanako"]);
+    }
+
+    #[test]
+    fn sort_str_by_key() {
+        let mut x = vec!["Rust", "is", "fast", "and", "memory-efficient", "with", "no", "GC"];
+
+        assert_eq!(sort_by_key(&mut x, &|s: &&str| s.to_lowercase()), Ok(()));
+
+        assert_eq!(x, vec!["and", "fast", "GC", "is", "memory-efficient", "no", "Rust", "with"]);
+    }
+
+    #[test]
+    fn sort_by_cached_key_calls_key_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let original: Vec<String> = new_u32_vec(1000).iter().map(|v| (v % 100).to_string()).collect();
+        let mut x = original.clone();
+        let calls = AtomicUsize::new(0);
+
+        assert_eq!(
+            sort_by_cached_key(&mut x, &|s: &String| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                s.parse::<u32>().unwrap()
+            }),
+            Ok(())
+        );
+
+        assert_eq!(calls.load(Ordering::SeqCst), original.len());
+        // 数値としての順序で、同じ値の中では入力順 (安定)
+        let mut expected = original;
+        expected.sort_by_key(|s| s.parse::<u32>().unwrap());
+        assert_eq!(x, expected);
+    }
+
+    #[test]
+    fn sort_f64_nan_policy() {
+        let input = vec![3.0, f64::NAN, -1.0, 0.0, f64::INFINITY, -0.0, 2.5];
+
+        let mut x = input.clone();
+        assert_eq!(sort_floats(&mut x, &Asc, NanPolicy::NanLast), Ok(()));
+        assert_eq!(&x[..6], &[-1.0, 0.0, -0.0, 2.5, 3.0, f64::INFINITY][..]);
+        assert!(x[6].is_nan());
+
+        let mut x = input.clone();
+        assert_eq!(sort_floats(&mut x, &Desc, NanPolicy::NanFirst), Ok(()));
+        assert!(x[0].is_nan());
+        assert_eq!(&x[1..], &[f64::INFINITY, 3.0, 2.5, 0.0, 0.0, -1.0][..]);
+
+        // -0.0 と +0.0 を区別する
+        let mut x = input.clone();
+        assert_eq!(sort_floats(&mut x, &Asc, NanPolicy::TotalOrder), Ok(()));
+        let bits: Vec<u64> = x[..6].iter().map(|v| v.to_bits()).collect();
+        let expected: Vec<u64> = [-1.0, -0.0, 0.0, 2.5, 3.0, f64::INFINITY].iter().map(|v: &f64| v.to_bits()).collect();
+        assert_eq!(bits, expected);
+
+        // エラーの場合は x を変更しない
+        let mut x = input.clone();
+        assert_eq!(sort_floats(&mut x, &Asc, NanPolicy::Error), Err(SortError::NaN { index: 1 }));
+        assert_eq!(x[0], 3.0);
+
+        let mut x: Vec<f32> = vec![2.0, -3.5, 1.0];
+        assert_eq!(sort_floats(&mut x, &Asc, NanPolicy::Error), Ok(()));
+        assert_eq!(x, vec![-3.5, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn sort_floats_by_field() {
+        let mut x = vec![("a", 0.5), ("b", f64::NAN), ("c", -2.0), ("d", 1.5)];
+
+        assert_eq!(sort_floats_by(&mut x, &|v: &(&str, f64)| v.1, &Asc, NanPolicy::NanLast), Ok(()));
+        let names: Vec<&str> = x.iter().map(|v| v.0).collect();
+        assert_eq!(names, vec!["c", "a", "d", "b"]);
+
+        assert_eq!(
+            sort_floats_by(&mut x, &|v: &(&str, f64)| v.1, &Asc, NanPolicy::Error),
+            Err(SortError::NaN { index: 3 })
+        );
+    }
+
+    #[test]
+    fn sort_partial_f64() {
+        let mut x = vec![2.5, -1.0, 3.0, 0.5, 10.0];
+
+        assert_eq!(sort_partial(&mut x, &Asc), Ok(()));
+        assert_eq!(x, vec![-1.0, 0.5, 2.5, 3.0, 10.0]);
+
+        assert_eq!(sort_partial(&mut x, &Desc), Ok(()));
+        assert_eq!(x, vec![10.0, 3.0, 2.5, 0.5, -1.0]);
+    }
+
+    #[test]
+    fn sort_partial_reports_incomparable_pair() {
+        let input = vec![2.5, -1.0, f64::NAN, 0.5];
+        let mut x = input.clone();
+
+        match sort_partial(&mut x, &Asc) {
+            Err(SortError::Incomparable { left, right }) => {
+                assert!(left < right);
+                assert!(x[left].is_nan() || x[right].is_nan());
+            },
+            other => panic!("unexpected result: {:?}", other),
+        }
+        // エラーの場合は x を変更しない
+        assert_eq!(&x[..2], &input[..2]);
+        assert_eq!(x[3], input[3]);
+    }
+
+    #[test]
+    fn sort_partial_reports_same_pair_as_third() {
+        // NaN をいくつか混ぜて、比較できない組が複数ある入力でも third と同じ組を返すこと
+        for &len in &[2, 17, 100, 10000] {
+            let input: Vec<f64> = new_u32_vec(len).into_iter()
+                .map(|v| if v % 7 == 0 { f64::NAN } else { f64::from(v) })
+                .collect();
+            if input.iter().all(|v| !v.is_nan()) {
+                continue;
+            }
+            for order in &[Asc, Desc] {
+                let mut x = input.clone();
+                let mut y = input.clone();
+                let result = sort_partial(&mut x, order);
+                assert!(matches!(result, Err(SortError::Incomparable { .. })), "len = {}", len);
+                assert_eq!(result, third::sort_partial(&mut y, order), "len = {}", len);
+            }
+        }
+    }
+
+    #[test]
+    fn sort_partial_sets() {
+        // 包含関係で順序付けた集合は半順序なので、比較できない組がある
+        #[derive(Debug, PartialEq)]
+        struct Set(u32);
+
+        impl PartialOrd for Set {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                use std::cmp::Ordering::*;
+                match (self.0 & other.0 == self.0, self.0 & other.0 == other.0) {
+                    (true, true) => Some(Equal),
+                    (true, false) => Some(Less),
+                    (false, true) => Some(Greater),
+                    (false, false) => None,
+                }
+            }
+        }
+
+        // 全順序になっている (包含関係の鎖) 場合はソートできる
+        let mut chain = vec![Set(0b111), Set(0b001), Set(0b011)];
+        assert_eq!(sort_partial(&mut chain, &Asc), Ok(()));
+        assert_eq!(chain, vec![Set(0b001), Set(0b011), Set(0b111)]);
+
+        let mut x = vec![Set(0b001), Set(0b010), Set(0b011)];
+        assert_eq!(sort_partial(&mut x, &Asc), Err(SortError::Incomparable { left: 0, right: 1 }));
+    }
+
+    #[test]
+    fn argsort_u32() {
+        let x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
+
+        let perm = argsort(&x, &Asc).unwrap();
+        assert_eq!(perm, vec![4, 0, 2, 3, 6, 1, 7, 5]);
+        // x 自体は変更されない
+        assert_eq!(x, vec![10, 30, 11, 20, 4, 330, 21, 110]);
+
+        let sorted: Vec<u32> = perm.iter().map(|&i| x[i]).collect();
+        assert_eq!(sorted, vec![4, 10, 11, 20, 21, 30, 110, 330]);
+
+        assert_eq!(argsort(&x, &Desc).unwrap(), vec![5, 7, 1, 6, 3, 2, 0, 4]);
+    }
+
+    #[test]
+    fn argsort_columns() {
+        // 複数の列を 1 つの列の順序で並べ替える
+        let ages: Vec<u8> = vec![16, 14, 15, 14];
+        let mut names = vec!["Taro", "Hanako", "Kyoko", "Ryosuke"];
+
+        let perm = argsort_by(&ages, &|a, b| a.cmp(b)).unwrap();
+        // 同じ年齢 (14) の中では元の順序
+        assert_eq!(perm, vec![1, 3, 2, 0]);
+
+        apply_permutation(&mut names, &perm);
+        assert_eq!(names, vec!["Hanako", "Ryosuke", "Kyoko", "Taro"]);
+    }
+
+    #[test]
+    fn sort_pairs_u32_large() {
+        // 並列化のしきい値を超える長さで、組が崩れていないこと
+        for &len in &[3, 10007, 65536] {
+            let mut keys = new_u32_vec(len);
+            let mut values: Vec<u64> = keys.iter().map(|&k| k as u64 * 3).collect();
+
+            assert_eq!(sort_pairs(&mut keys, &mut values, &Asc), Ok(()));
+
+            assert!(is_sorted(&keys, &Asc));
+            for (&k, &v) in keys.iter().zip(values.iter()) {
+                assert_eq!(k as u64 * 3, v);
+            }
+        }
+
+        let mut keys: Vec<u32> = vec![3, 1, 2];
+        assert_eq!(
+            sort_pairs(&mut keys, &mut [0; 4], &Asc),
+            Err(SortError::LengthMismatch { keys: 3, values: 4 })
+        );
+    }
+
+    #[test]
+    fn sort_pairs_with_config() {
+        let pool = Arc::new(rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap());
+        let configs = vec![
+            ParallelConfig { threshold: 0, ..Default::default() },
+            ParallelConfig { threshold: 1, leaf: LeafConfig::disabled(), ..Default::default() },
+            ParallelConfig { threshold: 7, leaf: LeafConfig { size: 5, sort: LeafSort::Insertion }, ..Default::default() },
+            ParallelConfig { threshold: 1, pool: Some(pool), ..Default::default() },
+        ];
+
+        for config in &configs {
+            for &len in &[8, 13, 1000] {
+                // 重複したキーでも、値が同じ swap で動いていること
+                let mut keys: Vec<u32> = new_u32_vec(len).into_iter().map(|v| v % 16).collect();
+                let mut values: Vec<u64> = keys.iter().map(|&k| k as u64 * 3).collect();
+
+                assert_eq!(sort_pairs_by_with_config(&mut keys, &mut values, &|a, b| b.cmp(a), config), Ok(()));
+
+                assert!(is_sorted(&keys, &Desc), "len = {}, config = {:?}", len, config);
+                for (&k, &v) in keys.iter().zip(values.iter()) {
+                    assert_eq!(k as u64 * 3, v);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn comparator_panic() {
+        let configs = vec![
+            ParallelConfig::default(),
+            ParallelConfig { threshold: 1, ..Default::default() },
+        ];
+        for config in &configs {
+            let mut x = new_u32_vec(1000);
+            let mut expected = x.clone();
+            expected.sort();
+
+            let result = sort_by_with_config(&mut x, &|a: &u32, b: &u32| {
+                if *a == expected[500] {
+                    panic!("comparator panicked");
+                }
+                a.cmp(b)
+            }, config);
+            assert_eq!(result, Err(SortError::ComparatorPanic), "config = {:?}", config);
+
+            // 要素は失われず、並べ替えただけになっている
+            x.sort();
+            assert_eq!(x, expected);
+        }
+    }
+
+    #[test]
+    fn cancel() {
+        // ソートの前にキャンセルされていれば、何も比較しない
+        let token = CancelToken::new();
+        token.cancel();
+        let config = ParallelConfig { cancel: Some(token), ..Default::default() };
+        let mut x = new_u32_vec(1000);
+        let input = x.clone();
+        assert_eq!(sort_by_with_config(&mut x, &|a, b| a.cmp(b), &config), Err(SortError::Cancelled));
+        assert_eq!(x, input);
+
+        // 途中でキャンセルすると、そこで止まる
+        let token = CancelToken::new();
+        let config = ParallelConfig { threshold: 64, cancel: Some(token.clone()), ..Default::default() };
+        let count = AtomicUsize::new(0);
+        let mut x = new_u32_vec(100_000);
+        let mut keys = x.clone();
+        assert_eq!(sort_by_with_config(&mut x, &|a: &u32, b: &u32| {
+            if count.fetch_add(1, AtomicOrdering::Relaxed) == 1000 {
+                token.cancel();
+            }
+            a.cmp(b)
+        }, &config), Err(SortError::Cancelled));
+        assert!(count.into_inner() < network(100_000, &config).unwrap().size());
+        let mut values = keys.clone();
+        assert_eq!(sort_pairs_by_with_config(&mut keys, &mut values, &|a, b| a.cmp(b), &config), Err(SortError::Cancelled));
+
+        // キャンセルしなければ最後までソートする
+        let config = ParallelConfig { cancel: Some(CancelToken::new()), ..Default::default() };
+        assert_eq!(sort_by_with_config(&mut x, &|a, b| a.cmp(b), &config), Ok(()));
+        assert!(is_sorted(&x, &Asc));
+    }
+
+    #[test]
+    fn bitonic_merge_u32_large() {
+        let len = 100_000;
+        for &mid in &[0, 1, 4096, 33_333, 50_000, len] {
+            for order in &[Asc, Desc] {
+                let mut x = new_u32_vec(len);
+                assert_eq!(sort(&mut x[..mid], order), Ok(()));
+                assert_eq!(sort(&mut x[mid..], order), Ok(()));
+
+                assert_eq!(bitonic_merge(&mut x, mid, order), Ok(()));
+                assert!(is_sorted(&x, order), "mid = {}", mid);
+            }
+        }
+    }
+
+    #[test]
+    fn merge_runs_same_as_third() {
+        let runs = [5000, 0, 17, 1, 10000, 33, 4096, 64, 9000];
+        let len = runs.iter().sum();
+
+        for order in &[Asc, Desc] {
+            let mut x = new_u32_vec(len);
+            let mut start = 0;
+            for &run in runs.iter() {
+                assert_eq!(sort(&mut x[start..start + run], order), Ok(()));
+                start += run;
+            }
+            let mut expected = x.clone();
+            assert_eq!(crate::third::merge_runs(&mut expected, &runs, order), Ok(()));
+
+            assert_eq!(merge_runs(&mut x, &runs, order), Ok(()));
+            assert_eq!(x, expected);
+        }
+
+        let mut x = vec![1u32, 2, 3];
+        assert_eq!(merge_runs(&mut x, &[2], &Asc), Err(SortError::InvalidLength { len: 3 }));
+    }
+
+    #[test]
+    fn partial_sort_u32_large() {
+        let len = 100_000;
+        for &k in &[1, 100, 4096, 50_000, len] {
+            for order in &[Asc, Desc] {
+                let original = new_u32_vec(len);
+                let mut expected = original.clone();
+                assert_eq!(sort(&mut expected, order), Ok(()));
+
+                let mut x = original.clone();
+                assert_eq!(partial_sort(&mut x, k, order), Ok(()));
+                assert_eq!(x[..k], expected[..k], "k = {}", k);
+
+                assert_eq!(sort(&mut x, order), Ok(()));
+                assert_eq!(x, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn top_k_from_parallel_iterator() {
+        let x = new_u32_vec(100_000);
+        let mut expected = x.clone();
+        assert_eq!(sort(&mut expected, &Asc), Ok(()));
+
+        for &k in &[1, 10, 1000, 100_000] {
+            let actual = top_k(x.par_iter().copied(), k, &|a: &u32, b: &u32| a.cmp(b));
+            assert_eq!(actual, Ok(expected[..k].to_vec()), "k = {}", k);
+        }
+        assert_eq!(top_k(x.par_iter(), 0, &|a: &&u32, b: &&u32| a.cmp(b)), Ok(vec![]));
+        assert_eq!(top_k(Vec::<u32>::new(), 5, &|a: &u32, b: &u32| a.cmp(b)), Ok(vec![]));
+        assert_eq!(top_k(vec![3u32, 1, 2], 5, &|a: &u32, b: &u32| b.cmp(a)), Ok(vec![3, 2, 1]));
+    }
+}
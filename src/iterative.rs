@@ -0,0 +1,307 @@
+use super::{SortError, SortOrder};
+use crate::fourth::PARALLEL_THRESHOLD;
+use crate::sorter::{Capabilities, Sorter};
+use crate::network::Network;
+use rayon::prelude::*;
+use std::cmp::Ordering;
+
+// third, fourth は do_sort -> sub_sort -> compare_and_swap と再帰で bitonic network を辿るが、
+// このモジュールでは bitonic sort を「段 (stage) の列」として明示的にループで実行する。
+//
+// 各段ではインデックスの組 (i, partner(i)) を比較・交換する。third の任意長 network (前半を逆順にソートする) とは違い、
+// 比較する組をすべて「小さい方を前 (インデックスが小さい側) に置く」向きに揃えた変種を使っていて、
+// 長さ n が 2 の冪乗でない場合は、n 以上のインデックスに +∞ が並んでいるとみなして
+// その組の比較を飛ばすだけで済む (+∞ との比較は交換が起きないため)。
+// third とは比較器の並びが違うので、comparator が等しいとみなす要素同士の並び順は third と一致しない。
+// 全順序の comparator (等しいとみなす要素が同じ値しかない) なら、結果は third と同じになる。
+
+/// bitonic network の 1 段
+///
+/// 長さ k の bitonic 列を作る段のうち、比較距離が j の段を表す。
+/// j == k / 2 の段は、ブロックの前半と (反転した) 後半を比較して、2 つのソート済み列を bitonic 列にする段。
+/// それ以外の段は、距離 j 離れた要素同士を比較する half-cleaner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stage {
+    /// ソート済みにするブロックの長さ (2 の冪乗)
+    pub k: usize,
+    /// 比較する要素の距離 (2 の冪乗、j < k)
+    pub j: usize,
+}
+
+impl Stage {
+    /// インデックス i と比較する相手のインデックス
+    pub fn partner(&self, i: usize) -> usize {
+        if self.j == self.k / 2 {
+            // ブロック内で反対側 (k - 1 - i) の要素と比較する
+            i ^ (self.k - 1)
+        } else {
+            i ^ self.j
+        }
+    }
+}
+
+/// 長さ n のスライスをソートする段を、実行する順に返す
+pub fn stages(n: usize) -> Vec<Stage> {
+    let size = n.next_power_of_two();
+    let mut stages = Vec::new();
+
+    let mut k = 2;
+    while k <= size {
+        let mut j = k / 2;
+        while j > 0 {
+            stages.push(Stage { k, j });
+            j /= 2;
+        }
+        k *= 2;
+    }
+    stages
+}
+
+/// 長さ n のスライスに対して sort_by が実行するネットワーク
+pub fn network(n: usize) -> Network {
+    let comparators = stages(n).into_iter()
+        .flat_map(move |stage| (0..n).map(move |i| (i, stage.partner(i))))
+        .filter(move |&(i, l)| i < l && l < n);
+    Network::from_comparators(n, comparators)
+}
+
+pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    match *order {
+        SortOrder::Ascending  => sort_by(x, &|a, b| a.cmp(b)),
+        SortOrder::Descending => sort_by(x, &|a, b| b.cmp(a)),
+    }
+}
+
+pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+    where F: Fn(&T, &T) -> Ordering
+{
+    for stage in stages(x.len()) {
+        run_stage(x, stage, comparator);
+    }
+    Ok(())
+}
+
+/// 各段の中の比較を rayon で並列に実行する
+pub fn par_sort<T: Ord + Send>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    match *order {
+        SortOrder::Ascending  => par_sort_by(x, &|a, b| a.cmp(b)),
+        SortOrder::Descending => par_sort_by(x, &|a, b| b.cmp(a)),
+    }
+}
+
+pub fn par_sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+    where T: Send,
+        F: Sync + Fn(&T, &T) -> Ordering,
+{
+    for stage in stages(x.len()) {
+        // 段と段の間には依存関係があるので、並列化するのは段の中だけ
+        par_run_stage(x, stage, comparator);
+    }
+    Ok(())
+}
+
+/// 1 段分の比較・交換をインデックス計算でそのまま実行する
+pub(crate) fn run_stage<T, F>(x: &mut [T], stage: Stage, comparator: &F)
+    where F: Fn(&T, &T) -> Ordering
+{
+    let n = x.len();
+    for i in 0..n {
+        let l = stage.partner(i);
+        // 組は (i, l) と (l, i) の 2 回出てくるので、i < l の方だけ実行する
+        if i < l && l < n && comparator(&x[i], &x[l]) == Ordering::Greater {
+            x.swap(i, l);
+        }
+    }
+}
+
+/// 1 段分の比較・交換を並列に実行する
+///
+/// 同じ段の組は互いに重ならず、しかも長さ k (反転する段) または 2j (half-cleaner) のブロックの中で閉じている。
+/// なので par_chunks_mut でブロックに分け、さらにブロックを split_at_mut で前後に分けて組を zip すれば、
+/// インデックス計算をせずに安全に並列化できる
+fn par_run_stage<T, F>(x: &mut [T], stage: Stage, comparator: &F)
+    where T: Send,
+        F: Sync + Fn(&T, &T) -> Ordering,
+{
+    let half = stage.j;
+    let block = half * 2;
+    // ブロックが小さい段では、1 タスクがまとめて処理するブロック数を増やす
+    let min_blocks = (PARALLEL_THRESHOLD / block).max(1);
+
+    x.par_chunks_mut(block)
+        .with_min_len(min_blocks)
+        .for_each(|chunk| {
+            if chunk.len() <= half {
+                // 後半が丸ごと n の外にあるブロックは比較する組がない
+                return;
+            }
+            let (first, second) = chunk.split_at_mut(half);
+            let pairs = second.len();
+
+            if stage.j == stage.k / 2 {
+                // first[i] と second[k/2 - 1 - i] が組になる。後半が欠けている分、前半の先頭側の組がなくなる
+                first[half - pairs..].par_iter_mut()
+                    .zip(second.par_iter_mut().rev())
+                    .with_min_len(PARALLEL_THRESHOLD)
+                    .for_each(|(a, b)| compare_and_swap(a, b, comparator));
+            } else {
+                first[..pairs].par_iter_mut()
+                    .zip(second.par_iter_mut())
+                    .with_min_len(PARALLEL_THRESHOLD)
+                    .for_each(|(a, b)| compare_and_swap(a, b, comparator));
+            }
+        });
+}
+
+fn compare_and_swap<T, F>(a: &mut T, b: &mut T, comparator: &F)
+    where F: Fn(&T, &T) -> Ordering
+{
+    if comparator(a, b) == Ordering::Greater {
+        std::mem::swap(a, b);
+    }
+}
+
+/// `Sorter` トレイトで iterative の sort_by (段の列として実行する bitonic sort) を呼び出すための型
+pub struct Iterative;
+
+impl<T: Ord> Sorter<T> for Iterative {
+    fn name(&self) -> &'static str {
+        "iterative"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { parallel: false, generic: true, power_of_two_only: false, comparator: true }
+    }
+
+    fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+        sort(x, order)
+    }
+
+    fn sort_by(&self, x: &mut [T], comparator: &(dyn Fn(&T, &T) -> Ordering + Sync)) -> Result<(), SortError> {
+        sort_by(x, &|a: &T, b: &T| comparator(a, b))
+    }
+}
+
+/// `Sorter` トレイトで iterative の par_sort_by を呼び出すための型
+pub struct ParIterative;
+
+impl<T: Ord + Send> Sorter<T> for ParIterative {
+    fn name(&self) -> &'static str {
+        "iterative_par"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { parallel: true, generic: true, power_of_two_only: false, comparator: true }
+    }
+
+    fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+        par_sort(x, order)
+    }
+
+    fn sort_by(&self, x: &mut [T], comparator: &(dyn Fn(&T, &T) -> Ordering + Sync)) -> Result<(), SortError> {
+        par_sort_by(x, &|a: &T, b: &T| comparator(a, b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{network, par_sort, par_sort_by, sort, sort_by, stages, Stage};
+    use crate::leaf::LeafConfig;
+    use crate::third;
+    use crate::SortOrder::{Ascending as Asc, Descending as Desc};
+    use crate::utils::{is_sorted, new_u32_vec};
+    use std::cmp::Ordering;
+
+    #[test]
+    fn stages_of_eight() {
+        let actual: Vec<(usize, usize)> = stages(8).iter().map(|s| (s.k, s.j)).collect();
+        assert_eq!(actual, vec![(2, 1), (4, 2), (4, 1), (8, 4), (8, 2), (8, 1)]);
+
+        assert!(stages(0).is_empty());
+        assert!(stages(1).is_empty());
+        // 2 の冪乗でない長さは、次の 2 の冪乗と同じ段を使う
+        assert_eq!(stages(5), stages(8));
+    }
+
+    #[test]
+    fn stage_partner() {
+        let flip = Stage { k: 8, j: 4 };
+        assert_eq!((0..8).map(|i| flip.partner(i)).collect::<Vec<_>>(), vec![7, 6, 5, 4, 3, 2, 1, 0]);
+
+        let half_clean = Stage { k: 8, j: 2 };
+        assert_eq!((0..8).map(|i| half_clean.partner(i)).collect::<Vec<_>>(), vec![2, 3, 0, 1, 6, 7, 4, 5]);
+    }
+
+    #[test]
+    fn sort_str_ascending() {
+        let mut x = vec!["Rust", "is", "fast", "and", "memory-efficient", "with", "no", "GC"];
+
+        assert_eq!(sort(&mut x, &Asc), Ok(()));
+
+        assert_eq!(x, vec!["GC", "Rust", "and", "fast", "is", "memory-efficient", "no", "with"]);
+    }
+
+    #[test]
+    fn same_output_as_third_for_total_order() {
+        // キーが重複していても、元の位置で順序を決める comparator なら third と同じ結果になる
+        type Comparator = dyn Fn(&(u32, usize), &(u32, usize)) -> Ordering + Sync;
+        for &len in &[0, 1, 2, 3, 5, 8, 13, 100, 1000, 4096, 5000, 70000] {
+            let input: Vec<(u32, usize)> = new_u32_vec(len).into_iter().map(|v| v % 16).zip(0..).collect();
+            let comparators: [&Comparator; 2] = [
+                &|a, b| a.cmp(b),
+                &|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)),
+            ];
+
+            for comparator in comparators.iter() {
+                let mut expected = input.clone();
+                assert_eq!(third::sort_by(&mut expected, comparator), Ok(()));
+
+                let mut x = input.clone();
+                assert_eq!(sort_by(&mut x, comparator), Ok(()));
+                assert_eq!(x, expected, "len = {}", len);
+
+                let mut x = input.clone();
+                assert_eq!(par_sort_by(&mut x, comparator), Ok(()));
+                assert_eq!(x, expected, "len = {}", len);
+            }
+        }
+    }
+
+    #[test]
+    fn same_comparators_as_network() {
+        // third とは別のネットワーク
+        assert_ne!(network(8), third::network(8, &LeafConfig::disabled()).unwrap());
+
+        // キーが重複していると、比較器の並びが違えば同じキーの要素の並び順が変わる
+        for n in 0..=100 {
+            let input: Vec<(u32, usize)> = new_u32_vec(n).into_iter().map(|v| v % 8).zip(0..).collect();
+            let comparator = |a: &(u32, usize), b: &(u32, usize)| b.0.cmp(&a.0);
+
+            let mut expected = input.clone();
+            assert_eq!(network(n).apply(&mut expected, &comparator), Ok(()));
+
+            let mut x = input.clone();
+            assert_eq!(sort_by(&mut x, &comparator), Ok(()));
+            assert_eq!(x, expected, "n = {}", n);
+
+            let mut x = input;
+            assert_eq!(par_sort_by(&mut x, &comparator), Ok(()));
+            assert_eq!(x, expected, "n = {}", n);
+        }
+    }
+
+    #[test]
+    fn sort_u32_large() {
+        for &len in &[4096, 5000, 70000] {
+            for order in &[Asc, Desc] {
+                let mut x = new_u32_vec(len);
+                assert_eq!(sort(&mut x, order), Ok(()));
+                assert!(is_sorted(&x, order));
+
+                let mut x = new_u32_vec(len);
+                assert_eq!(par_sort(&mut x, order), Ok(()));
+                assert!(is_sorted(&x, order));
+            }
+        }
+    }
+}
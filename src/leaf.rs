@@ -0,0 +1,221 @@
+use crate::storage::{ByComparator, SortStorage};
+use std::cmp::Ordering;
+
+// bitonic sort の再帰を 1 要素になるまで続けるのは無駄が多いので、
+// ある程度小さいブロック (leaf) になったら挿入ソートか固定のソーティングネットワークでまとめてソートする。
+// bitonic のマージ段は、こうしてソートしたブロックに対して動く。
+
+/// leaf のブロックをソートする方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeafSort {
+    /// 挿入ソート。ほぼ整列済みの入力に強い
+    Insertion,
+    /// 要素数ごとに固定したソーティングネットワーク。比較の順序が入力によらない
+    Network,
+}
+
+/// Network で扱える最大の要素数
+pub const MAX_NETWORK_SIZE: usize = 16;
+
+/// LeafConfig::default() のブロックの大きさ
+pub const DEFAULT_LEAF_SIZE: usize = 16;
+
+/// 再帰を打ち切るブロックの大きさと、そのブロックのソート方法
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeafConfig {
+    /// 要素数がこの値以下になったら再帰をやめて leaf としてソートする。1 以下なら打ち切らない
+    pub size: usize,
+    pub sort: LeafSort,
+}
+
+impl Default for LeafConfig {
+    fn default() -> Self {
+        Self {
+            size: DEFAULT_LEAF_SIZE,
+            sort: LeafSort::Network,
+        }
+    }
+}
+
+impl LeafConfig {
+    /// 1 要素まで再帰する (leaf を使わない) 設定
+    pub fn disabled() -> Self {
+        Self { size: 1, sort: LeafSort::Insertion }
+    }
+
+    /// 実際に再帰を打ち切る要素数。Network は MAX_NETWORK_SIZE までしか扱えないのでそこで頭打ちにする
+    pub fn cutoff(&self) -> usize {
+        match self.sort {
+            LeafSort::Insertion => self.size,
+            LeafSort::Network => self.size.min(MAX_NETWORK_SIZE),
+        }
+    }
+}
+
+/// storage の lo から n 要素のブロックを forward (true なら compare の昇順) にソートする
+pub(crate) fn sort_leaf<S>(s: &mut S, lo: usize, n: usize, sort: LeafSort, forward: bool)
+    where S: SortStorage + ?Sized
+{
+    let swap_condition = if forward {
+        Ordering::Greater
+    } else {
+        Ordering::Less
+    };
+
+    match sort {
+        LeafSort::Insertion => {
+            // 隣同士の swap だけで並べ替える
+            for i in (lo + 1)..(lo + n) {
+                let mut j = i;
+                while j > lo && s.compare(j - 1, j) == swap_condition {
+                    s.swap(j - 1, j);
+                    j -= 1;
+                }
+            }
+        },
+        LeafSort::Network => {
+            for (i, j) in network(n) {
+                if s.compare(lo + i, lo + j) == swap_condition {
+                    s.swap(lo + i, lo + j);
+                }
+            }
+        },
+    }
+}
+
+/// 挿入ソート
+pub fn insertion_sort_by<T, F>(x: &mut [T], comparator: &F)
+    where F: Fn(&T, &T) -> Ordering
+{
+    let n = x.len();
+    sort_leaf(&mut ByComparator::new(x, comparator), 0, n, LeafSort::Insertion, true);
+}
+
+/// 固定のソーティングネットワークでソートする (x.len() <= MAX_NETWORK_SIZE)
+pub fn network_sort_by<T, F>(x: &mut [T], comparator: &F)
+    where F: Fn(&T, &T) -> Ordering
+{
+    let n = x.len();
+    sort_leaf(&mut ByComparator::new(x, comparator), 0, n, LeafSort::Network, true);
+}
+
+/// 要素数 n をソートするネットワークの比較器 (i, j) を順に返す。i < j で、小さい方が i に来る
+///
+/// n = 2..=8 と 16 は比較回数が最小のネットワークをそのまま使う。
+/// 9..=15 は 16 要素のネットワークから、n 以上のインデックスに触れる比較器を取り除いたものを使う
+/// (n 以降に +∞ が並んでいるとみなせば、その比較器では交換が起きないため)
+pub fn network(n: usize) -> impl Iterator<Item = (usize, usize)> {
+    assert!(n <= MAX_NETWORK_SIZE, "no sorting network for {} elements", n);
+
+    let comparators: &'static [(usize, usize)] = match n {
+        0 | 1 => &[],
+        2 => &NETWORK_2,
+        3 => &NETWORK_3,
+        4 => &NETWORK_4,
+        5 => &NETWORK_5,
+        6 => &NETWORK_6,
+        7 => &NETWORK_7,
+        8 => &NETWORK_8,
+        _ => &NETWORK_16,
+    };
+    comparators.iter()
+        .copied()
+        .filter(move |&(_, j)| j < n)
+}
+
+const NETWORK_2: [(usize, usize); 1] = [(0, 1)];
+
+const NETWORK_3: [(usize, usize); 3] = [(0, 2), (0, 1), (1, 2)];
+
+const NETWORK_4: [(usize, usize); 5] = [(0, 2), (1, 3), (0, 1), (2, 3), (1, 2)];
+
+const NETWORK_5: [(usize, usize); 9] = [
+    (0, 3), (1, 4),
+    (0, 2), (1, 3),
+    (0, 1), (2, 4),
+    (1, 2), (3, 4),
+    (2, 3),
+];
+
+const NETWORK_6: [(usize, usize); 12] = [
+    (0, 5), (1, 3), (2, 4),
+    (1, 2), (3, 4),
+    (0, 3), (2, 5),
+    (0, 1), (2, 3), (4, 5),
+    (1, 2), (3, 4),
+];
+
+const NETWORK_7: [(usize, usize); 16] = [
+    (0, 6), (2, 3), (4, 5),
+    (0, 2), (1, 4), (3, 6),
+    (0, 1), (2, 5), (3, 4),
+    (1, 2), (4, 6),
+    (2, 3), (4, 5),
+    (1, 2), (3, 4), (5, 6),
+];
+
+const NETWORK_8: [(usize, usize); 19] = [
+    (0, 2), (1, 3), (4, 6), (5, 7),
+    (0, 4), (1, 5), (2, 6), (3, 7),
+    (0, 1), (2, 3), (4, 5), (6, 7),
+    (2, 4), (3, 5),
+    (1, 4), (3, 6),
+    (1, 2), (3, 4), (5, 6),
+];
+
+const NETWORK_16: [(usize, usize); 60] = [
+    (0, 13), (1, 12), (2, 15), (3, 14), (4, 8), (5, 6), (7, 11), (9, 10),
+    (0, 5), (1, 7), (2, 9), (3, 4), (6, 13), (8, 14), (10, 15), (11, 12),
+    (0, 1), (2, 3), (4, 5), (6, 8), (7, 9), (10, 11), (12, 13), (14, 15),
+    (0, 2), (1, 3), (4, 10), (5, 11), (6, 7), (8, 9), (12, 14), (13, 15),
+    (1, 2), (3, 12), (4, 6), (5, 7), (8, 10), (9, 11), (13, 14),
+    (1, 4), (2, 6), (5, 8), (7, 10), (9, 13), (11, 14),
+    (2, 4), (3, 6), (9, 12), (11, 13),
+    (3, 5), (6, 8), (7, 9), (10, 12),
+    (3, 4), (5, 6), (7, 8), (9, 10), (11, 12),
+    (6, 7), (8, 9),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{insertion_sort_by, network, network_sort_by, LeafConfig, LeafSort, MAX_NETWORK_SIZE};
+    use crate::utils::new_u32_vec;
+
+    #[test]
+    fn networks_sort_every_zero_one_input() {
+        // 0-1 原理: 0 と 1 だけからなる入力をすべてソートできれば、任意の入力をソートできる
+        for n in 0..=MAX_NETWORK_SIZE {
+            for bits in 0..(1u32 << n) {
+                let mut x: Vec<u32> = (0..n).map(|i| (bits >> i) & 1).collect();
+                network_sort_by(&mut x, &|a, b| a.cmp(b));
+                assert!(x.windows(2).all(|w| w[0] <= w[1]), "n = {}, input = {:b}", n, bits);
+            }
+        }
+    }
+
+    #[test]
+    fn network_sizes() {
+        let sizes: Vec<usize> = (2..=8).map(|n| network(n).count()).collect();
+        assert_eq!(sizes, vec![1, 3, 5, 9, 12, 16, 19]);
+        assert_eq!(network(16).count(), 60);
+    }
+
+    #[test]
+    fn insertion_sort_u32() {
+        for &len in &[0, 1, 2, 16, 100] {
+            let mut x = new_u32_vec(len);
+            let mut expected = x.clone();
+            expected.sort();
+
+            insertion_sort_by(&mut x, &|a, b| a.cmp(b));
+            assert_eq!(x, expected);
+        }
+    }
+
+    #[test]
+    fn cutoff() {
+        assert_eq!(LeafConfig { size: 32, sort: LeafSort::Insertion }.cutoff(), 32);
+        assert_eq!(LeafConfig { size: 32, sort: LeafSort::Network }.cutoff(), 16);
+        assert_eq!(LeafConfig::disabled().cutoff(), 1);
+    }
+}
@@ -1,7 +1,25 @@
+// #[derive(SortKey)] が生成するコードは ::rust_sort のパスを使うので、このクレートの中からも同じパスで参照できるようにする
+extern crate self as rust_sort;
+
 pub mod first;
 pub mod second;
 pub mod third;
 pub mod fourth;
+pub mod iterative;
+pub mod leaf;
+pub mod float;
+pub mod sorter;
+pub mod storage;
+pub mod select;
+pub mod array;
+pub mod sort_key;
+pub mod network;
+pub mod odd_even;
+pub mod pairwise;
+pub mod verify;
+pub mod codegen;
+#[cfg(feature = "simd")]
+pub mod simd;
 pub mod utils;
 pub mod thread_example;
 
@@ -12,4 +30,122 @@ pub enum SortOrder {
     Ascending,
     /// 降順
     Descending,
-}
\ No newline at end of file
+}
+
+/// 浮動小数点数をソートするときの NaN の扱い
+/// 
+/// f32, f64 は PartialOrd しか実装しておらず、NaN は他のどの値とも比較できないので、
+/// ソート関数の引数で扱いを決める
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NanPolicy {
+    /// NaN をソート順序にかかわらず先頭に置く
+    NanFirst,
+    /// NaN をソート順序にかかわらず末尾に置く
+    NanLast,
+    /// NaN があればソートせずに `SortError::NaN` を返す
+    Error,
+    /// IEEE 754 の totalOrder に従う (`f64::total_cmp`)。-0.0 < +0.0 と区別し、
+    /// 符号ビットが立った NaN は -∞ より前、それ以外の NaN は +∞ より後ろに置く
+    TotalOrder,
+}
+
+/// ソート関数が返すエラー
+/// 
+/// 以前は `Result<(), String>` でエラーメッセージを返していたが、呼び出し側で文字列比較をしなくて済むように
+/// enum にしてエラーの種類と付随する情報 (長さやインデックス) を構造化して持たせる
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SortError {
+    /// スライスの長さがそのソート実装で扱えない (例: 2 の冪乗でない)
+    InvalidLength {
+        /// 渡されたスライスの長さ
+        len: usize,
+    },
+    /// 比較関数 (comparator) がソートの途中で panic した
+    ///
+    /// panic は third, fourth の sort_by で捕まえる。要素は入れ替えでしか動かさないので、
+    /// このとき x は元の要素を並べ替えたものになっている (ソートされているとは限らない)
+    ComparatorPanic,
+    /// ソートが完了する前に `fourth::CancelToken` でキャンセルされた
+    Cancelled,
+    /// 比較できない要素の組 (例: `partial_cmp` が `None` を返した) が見つかった
+    Incomparable {
+        /// 比較できなかった組の左側のインデックス
+        left: usize,
+        /// 比較できなかった組の右側のインデックス
+        right: usize,
+    },
+    /// 一緒に並べ替える 2 つのスライスの長さが違う
+    LengthMismatch {
+        /// キーのスライスの長さ
+        keys: usize,
+        /// 値のスライスの長さ
+        values: usize,
+    },
+    /// NaN を許さない設定で NaN が見つかった
+    NaN {
+        /// 最初に見つかった NaN のインデックス
+        index: usize,
+    },
+    /// 0.0 以上 1.0 以下でない (NaN を含む) 分位点が指定された
+    InvalidQuantile {
+        /// 分位点のスライスの中でのインデックス
+        index: usize,
+    },
+    /// その sorter が対応していない操作 (例: comparator を受け取らない実装での `sort_by`)
+    Unsupported {
+        /// 操作を受け付けなかった sorter の名前
+        sorter: &'static str,
+    },
+}
+
+impl std::fmt::Display for SortError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            SortError::InvalidLength { len } =>
+                write!(f, "invalid length for this sort. x.len(): {}", len),
+            SortError::ComparatorPanic =>
+                write!(f, "the comparator panicked during the sort"),
+            SortError::Cancelled =>
+                write!(f, "the sort was cancelled"),
+            SortError::Incomparable { left, right } =>
+                write!(f, "elements at {} and {} are not comparable", left, right),
+            SortError::LengthMismatch { keys, values } =>
+                write!(f, "keys and values have different lengths. keys.len(): {}, values.len(): {}", keys, values),
+            SortError::NaN { index } =>
+                write!(f, "NaN found at index {}", index),
+            SortError::InvalidQuantile { index } =>
+                write!(f, "quantile at index {} is not in 0.0..=1.0", index),
+            SortError::Unsupported { sorter } =>
+                write!(f, "the operation is not supported by the {} sorter", sorter),
+        }
+    }
+}
+
+impl std::error::Error for SortError {}
+
+#[cfg(test)]
+mod tests {
+    use super::SortError;
+
+    #[test]
+    fn sort_error_display() {
+        assert_eq!(
+            SortError::InvalidLength { len: 3 }.to_string(),
+            "invalid length for this sort. x.len(): 3"
+        );
+        assert_eq!(
+            SortError::Incomparable { left: 1, right: 4 }.to_string(),
+            "elements at 1 and 4 are not comparable"
+        );
+
+        assert_eq!(SortError::ComparatorPanic.to_string(), "the comparator panicked during the sort");
+        assert_eq!(
+            SortError::InvalidQuantile { index: 2 }.to_string(),
+            "quantile at index 2 is not in 0.0..=1.0"
+        );
+
+        // std::error::Error として Box に詰められること
+        let e: Box<dyn std::error::Error> = Box::new(SortError::Cancelled);
+        assert_eq!(e.to_string(), "the sort was cancelled");
+    }
+}
@@ -0,0 +1,603 @@
+use super::SortError;
+use crate::leaf::LeafConfig;
+use crate::{odd_even, pairwise, third};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+// third, fourth, iterative, odd_even, pairwise はソーティングネットワークをその場で辿りながら実行するが、
+// このモジュールではネットワークそのもの (どの段でどの組を比較するか) をデータとして扱う。
+// 比較器の数や段数を調べたり、別の種類のネットワークと比べたり、テキストや JSON に読み書きしたりするのに使う。
+//
+// ネットワークの作り方は各モジュールの実行方法が唯一の定義で、ここではそれを実行して比較器を記録するだけにする
+// (同じネットワークを別の実装で作ると、片方だけ変わったときに食い違うため)。
+//
+// 比較器 (i, j) は「x[i] と x[j] を比較して、小さい方を x[i]、大きい方を x[j] に置く」ことを表す。
+// bitonic network は逆向きの比較器も使うので、i > j の比較器もありうる。
+
+/// 段 (互いに重ならない比較器の集まり) の列で表したソーティングネットワーク
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Network {
+    n: usize,
+    stages: Vec<Vec<(usize, usize)>>,
+}
+
+impl Network {
+    /// 実行する順に並べた比較器から、n 入力のネットワークを作る
+    ///
+    /// 各比較器は、それが触れる 2 本の線で直前に使われた段の次の段に入れる (できるだけ前の段に詰める)。
+    /// 同じ線に触れる比較器の前後関係は変わらないので、順に実行した場合と結果は同じになる。
+    /// n 以上のインデックスや、i == j の比較器がある場合は panic する
+    pub fn from_comparators<I>(n: usize, comparators: I) -> Self
+        where I: IntoIterator<Item = (usize, usize)>
+    {
+        let mut stages: Vec<Vec<(usize, usize)>> = Vec::new();
+        // 各線を最後に使った段の次の段
+        let mut next_stage = vec![0; n];
+
+        for (i, j) in comparators {
+            assert!(i < n && j < n, "comparator ({}, {}) is out of range for {} inputs", i, j, n);
+            assert_ne!(i, j, "comparator must connect two different wires");
+
+            let stage = next_stage[i].max(next_stage[j]);
+            if stage == stages.len() {
+                stages.push(Vec::new());
+            }
+            stages[stage].push((i, j));
+            next_stage[i] = stage + 1;
+            next_stage[j] = stage + 1;
+        }
+        Self { n, stages }
+    }
+
+    /// third (leaf なし) が実行する任意長の bitonic network
+    pub fn bitonic(n: usize) -> Self {
+        third::network(n, &LeafConfig::disabled()).expect("third supports every length without a leaf")
+    }
+
+    /// Batcher の odd-even merge sort (odd_even が実行するネットワーク)
+    ///
+    /// 2 の冪乗の長さのネットワークから、n 以上のインデックスに触れる比較器を取り除いたもの。
+    /// 比較器はすべて小さい方を前に置く向きなので、n 以降に +∞ が並んでいるとみなせば取り除いても結果は変わらない
+    pub fn odd_even_merge(n: usize) -> Self {
+        odd_even::network(n)
+    }
+
+    /// Parberry の pairwise sorting network (pairwise が実行するネットワーク)
+    ///
+    /// odd_even_merge と同じく、2 の冪乗の長さのネットワークから n 以上のインデックスに触れる比較器を取り除いたもの
+    pub fn pairwise(n: usize) -> Self {
+        pairwise::network(n)
+    }
+
+    /// 段ごとに並べた比較器からネットワークを作る。段の分け方はそのまま使う
+    ///
+    /// n 以上のインデックス、i == j の比較器、同じ段で同じ線に触れる比較器がある場合はエラーを返す
+    pub fn from_stages(n: usize, stages: Vec<Vec<(usize, usize)>>) -> Result<Self, ParseError> {
+        for (stage, comparators) in stages.iter().enumerate() {
+            let mut used = vec![false; n];
+            for &(i, j) in comparators.iter() {
+                if i >= n || j >= n {
+                    return Err(ParseError::OutOfRange { stage, comparator: (i, j), n });
+                }
+                if i == j {
+                    return Err(ParseError::SameWire { stage, comparator: (i, j) });
+                }
+                if used[i] || used[j] {
+                    return Err(ParseError::Overlap { stage, comparator: (i, j) });
+                }
+                used[i] = true;
+                used[j] = true;
+            }
+        }
+        Ok(Self { n, stages })
+    }
+
+    /// 入力の数
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    pub fn stages(&self) -> &[Vec<(usize, usize)>] {
+        &self.stages
+    }
+
+    /// すべての比較器を実行する順に返す
+    pub fn comparators(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.stages.iter().flat_map(|stage| stage.iter().copied())
+    }
+
+    /// 比較器の数
+    pub fn size(&self) -> usize {
+        self.stages.iter().map(|stage| stage.len()).sum()
+    }
+
+    /// 段の数
+    pub fn depth(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// ネットワークを x に適用して、comparator の順に並べる。x.len() が n と違う場合は `SortError::InvalidLength` を返す
+    pub fn apply<T, F>(&self, x: &mut [T], comparator: &F) -> Result<(), SortError>
+        where F: Fn(&T, &T) -> Ordering
+    {
+        if x.len() != self.n {
+            return Err(SortError::InvalidLength { len: x.len() });
+        }
+        for (i, j) in self.comparators() {
+            if comparator(&x[i], &x[j]) == Ordering::Greater {
+                x.swap(i, j);
+            }
+        }
+        Ok(())
+    }
+
+    /// 1 行目に入力の数、2 行目以降に 1 段ずつ比較器を "i:j" の形で空白区切りで並べたテキスト
+    pub fn to_text(&self) -> String {
+        self.to_string()
+    }
+
+    /// `{"n":4,"stages":[[[0,1],[2,3]],...]}` の形の JSON
+    pub fn to_json(&self) -> String {
+        let stages: Vec<String> = self.stages.iter()
+            .map(|stage| {
+                let comparators: Vec<String> = stage.iter()
+                    .map(|&(i, j)| format!("[{},{}]", i, j))
+                    .collect();
+                format!("[{}]", comparators.join(","))
+            })
+            .collect();
+        format!("{{\"n\":{},\"stages\":[{}]}}", self.n, stages.join(","))
+    }
+
+    /// to_text の形式のテキストを読む。1 行目の "n=" の後に入力の数、空行は空の段として扱う
+    pub fn from_text(text: &str) -> Result<Self, ParseError> {
+        let mut lines = text.lines();
+        let first = lines.next().unwrap_or("");
+        let n = first.trim().strip_prefix("n=").ok_or(ParseError::MissingInputs)?;
+        let n = parse_index(n, 1, column(first, n))?;
+
+        let mut stages = Vec::new();
+        for (line, text) in (2..).zip(lines) {
+            let stage = text.split_whitespace()
+                .map(|comparator| {
+                    let start = column(text, comparator);
+                    let (i, j) = comparator.split_once(':')
+                        .ok_or(ParseError::InvalidComparator { line, column: start })?;
+                    Ok((parse_index(i, line, start)?, parse_index(j, line, column(text, j))?))
+                })
+                .collect::<Result<Vec<_>, ParseError>>()?;
+            stages.push(stage);
+        }
+        Self::from_stages(n, stages)
+    }
+
+    /// to_json の形式の JSON を読む。空白やキーの順序は問わない
+    ///
+    /// 読めるのは "n" と "stages" の 2 つのキーを持つオブジェクトだけで、"stages" は段の配列、段は [i, j] の配列に限る。
+    /// それより深い入れ子や、ほかのキー・型の値はエラーになる
+    pub fn from_json(json: &str) -> Result<Self, ParseError> {
+        let mut parser = JsonParser { text: json, pos: 0 };
+        let network = parser.network()?;
+        if parser.peek().is_some() {
+            return Err(parser.error("end of input"));
+        }
+        Ok(network)
+    }
+}
+
+/// from_stages, from_text, from_json が返すエラー
+///
+/// line, column は 1 から数えた行と列 (列はバイト単位)。stage は 0 から数えた段の番号で、
+/// to_text の形式では stage + 2 行目にあたる
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// テキストの 1 行目が "n=" で始まっていない
+    MissingInputs,
+    /// 入力の数やインデックスが 0 以上の整数として読めない
+    InvalidNumber { line: usize, column: usize },
+    /// テキストの比較器が "i:j" の形になっていない
+    InvalidComparator { line: usize, column: usize },
+    /// JSON の構文が違う。expected はその位置に期待したもの
+    Syntax { line: usize, column: usize, expected: &'static str },
+    /// JSON のオブジェクトに "n" か "stages" がない
+    MissingField { name: &'static str },
+    /// n 以上のインデックスに触れる比較器がある
+    OutOfRange { stage: usize, comparator: (usize, usize), n: usize },
+    /// 同じ線同士をつなぐ比較器 (i == j) がある
+    SameWire { stage: usize, comparator: (usize, usize) },
+    /// 同じ段に、同じ線に触れる比較器が 2 つ以上ある
+    Overlap { stage: usize, comparator: (usize, usize) },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ParseError::MissingInputs =>
+                write!(f, "the first line must be n=<number of inputs>"),
+            ParseError::InvalidNumber { line, column } =>
+                write!(f, "invalid number at line {}, column {}", line, column),
+            ParseError::InvalidComparator { line, column } =>
+                write!(f, "comparator must be i:j at line {}, column {}", line, column),
+            ParseError::Syntax { line, column, expected } =>
+                write!(f, "expected {} at line {}, column {}", expected, line, column),
+            ParseError::MissingField { name } =>
+                write!(f, "missing field \"{}\"", name),
+            ParseError::OutOfRange { stage, comparator: (i, j), n } =>
+                write!(f, "comparator ({}, {}) in stage {} is out of range for {} inputs", i, j, stage, n),
+            ParseError::SameWire { stage, comparator: (i, j) } =>
+                write!(f, "comparator ({}, {}) in stage {} must connect two different wires", i, j, stage),
+            ParseError::Overlap { stage, comparator: (i, j) } =>
+                write!(f, "comparator ({}, {}) overlaps another comparator in stage {}", i, j, stage),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// line の中での token の列 (1 から数えたバイト位置)。token は line の一部であること
+fn column(line: &str, token: &str) -> usize {
+    token.as_ptr() as usize - line.as_ptr() as usize + 1
+}
+
+fn parse_index(token: &str, line: usize, column: usize) -> Result<usize, ParseError> {
+    token.parse().map_err(|_| ParseError::InvalidNumber { line, column })
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "n={}", self.n)?;
+        for stage in self.stages.iter() {
+            let comparators: Vec<String> = stage.iter()
+                .map(|&(i, j)| format!("{}:{}", i, j))
+                .collect();
+            writeln!(f, "{}", comparators.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Network {
+    type Err = ParseError;
+
+    /// to_text の形式のテキストを読む
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_text(s)
+    }
+}
+
+/// data-oblivious なソート sort が、インデックスの列に対して実行する比較器を記録して n 入力のネットワークを作る
+///
+/// sort は comparator(a, b) を、a が小さい方を置く側になるように呼ぶこと。comparator は常に Equal を返すので
+/// 要素は動かず、比較される値がそのまま比較器のインデックスになる
+pub(crate) fn record<S>(n: usize, sort: S) -> Network
+    where S: FnOnce(&mut [usize], &dyn Fn(&usize, &usize) -> Ordering)
+{
+    let comparators = RefCell::new(Vec::new());
+    let mut wires: Vec<usize> = (0..n).collect();
+    sort(&mut wires, &|&i: &usize, &j: &usize| {
+        comparators.borrow_mut().push((i, j));
+        Ordering::Equal
+    });
+    Network::from_comparators(n, comparators.into_inner())
+}
+
+/// from_json 用の小さな JSON パーサ
+///
+/// 任意の JSON を読むのではなく、to_json の形 (n と段の配列を持つオブジェクト) だけを読む。
+/// 入れ子の深さが決まっているので再帰しない
+struct JsonParser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    /// 今の位置に expected がなかったことを表すエラー
+    fn error(&self, expected: &'static str) -> ParseError {
+        let before = &self.text[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let column = self.pos - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        ParseError::Syntax { line, column, expected }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        let bytes = self.text.as_bytes();
+        while self.pos < bytes.len() && bytes[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+        bytes.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: u8, expected: &'static str) -> Result<(), ParseError> {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(expected))
+        }
+    }
+
+    /// {"n": <数>, "stages": [<段>, ...]}
+    fn network(&mut self) -> Result<Network, ParseError> {
+        let mut n = None;
+        let mut stages = None;
+        self.expect(b'{', "'{'")?;
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+        } else {
+            loop {
+                let start = self.pos;
+                match self.string()?.as_str() {
+                    "n" => {
+                        self.expect(b':', "':'")?;
+                        n = Some(self.number()?);
+                    },
+                    "stages" => {
+                        self.expect(b':', "':'")?;
+                        stages = Some(self.array(|p| p.array(JsonParser::comparator))?);
+                    },
+                    _ => {
+                        self.pos = start;
+                        return Err(self.error("\"n\" or \"stages\""));
+                    },
+                }
+                if self.peek() == Some(b',') {
+                    self.pos += 1;
+                } else {
+                    self.expect(b'}', "',' or '}'")?;
+                    break;
+                }
+            }
+        }
+        let n = n.ok_or(ParseError::MissingField { name: "n" })?;
+        let stages = stages.ok_or(ParseError::MissingField { name: "stages" })?;
+        Network::from_stages(n, stages)
+    }
+
+    /// [i, j]
+    fn comparator(&mut self) -> Result<(usize, usize), ParseError> {
+        self.expect(b'[', "'['")?;
+        let i = self.number()?;
+        self.expect(b',', "','")?;
+        let j = self.number()?;
+        self.expect(b']', "']'")?;
+        Ok((i, j))
+    }
+
+    /// 要素を item で読む配列
+    fn array<T, F>(&mut self, mut item: F) -> Result<Vec<T>, ParseError>
+        where F: FnMut(&mut Self) -> Result<T, ParseError>
+    {
+        self.expect(b'[', "'['")?;
+        let mut items = Vec::new();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if self.peek() == Some(b',') {
+                self.pos += 1;
+            } else {
+                self.expect(b']', "',' or ']'")?;
+                return Ok(items);
+            }
+        }
+    }
+
+    /// 0 以上の整数
+    fn number(&mut self) -> Result<usize, ParseError> {
+        let start = match self.peek() {
+            Some(c) if c.is_ascii_digit() => self.pos,
+            _ => return Err(self.error("a number")),
+        };
+        let digits = self.text[start..].bytes().take_while(u8::is_ascii_digit).count();
+        let number = self.text[start..start + digits].parse().map_err(|_| self.error("a number that fits in usize"))?;
+        self.pos += digits;
+        Ok(number)
+    }
+
+    /// エスケープ (\uXXXX を含む) を解釈した文字列
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.expect(b'"', "a string")?;
+        let mut s = String::new();
+        let mut chars = self.text[self.pos..].char_indices();
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += offset + 1;
+                    return Ok(s);
+                },
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                            // サロゲートペアは名前に出てこないので、1 つの \uXXXX で表せる文字だけを扱う
+                            match u32::from_str_radix(&hex, 16).ok().filter(|_| hex.len() == 4).and_then(char::from_u32) {
+                                Some(c) => c,
+                                None => {
+                                    self.pos += offset;
+                                    return Err(self.error("a valid \\u escape"));
+                                },
+                            }
+                        },
+                        _ => {
+                            self.pos += offset;
+                            return Err(self.error("a valid escape"));
+                        },
+                    };
+                    s.push(escaped);
+                },
+                c if c < ' ' => {
+                    self.pos += offset;
+                    return Err(self.error("'\"'"));
+                },
+                c => s.push(c),
+            }
+        }
+        self.pos = self.text.len();
+        Err(self.error("'\"'"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Network, ParseError};
+    use crate::leaf::{self, LeafConfig};
+    use crate::third;
+    use crate::SortError;
+    use crate::utils::new_u32_vec;
+
+    #[test]
+    fn bitonic_same_as_third() {
+        for n in 0..=70 {
+            let network = Network::bitonic(n);
+            let input = new_u32_vec(n);
+
+            let mut expected = input.clone();
+            assert_eq!(third::sort_by_with_leaf(&mut expected, &|a, b| a.cmp(b), &LeafConfig::disabled()), Ok(()));
+            let mut x = input.clone();
+            assert_eq!(network.apply(&mut x, &|a, b| a.cmp(b)), Ok(()));
+            assert_eq!(x, expected, "n = {}", n);
+
+            let mut expected = input.clone();
+            assert_eq!(third::sort_by_with_leaf(&mut expected, &|a, b| b.cmp(a), &LeafConfig::disabled()), Ok(()));
+            let mut x = input;
+            assert_eq!(network.apply(&mut x, &|a, b| b.cmp(a)), Ok(()));
+            assert_eq!(x, expected, "n = {}", n);
+        }
+    }
+
+    #[test]
+    fn networks_sort_random_input() {
+        for n in 0..=100 {
+            for network in &[Network::odd_even_merge(n), Network::pairwise(n)] {
+                let mut x = new_u32_vec(n);
+                let mut expected = x.clone();
+                expected.sort();
+
+                assert_eq!(network.apply(&mut x, &|a, b| a.cmp(b)), Ok(()));
+                assert_eq!(x, expected, "n = {}", n);
+            }
+        }
+    }
+
+    #[test]
+    fn sizes_and_depths() {
+        let bitonic = Network::bitonic(16);
+        assert_eq!((bitonic.size(), bitonic.depth()), (80, 10));
+        let odd_even = Network::odd_even_merge(16);
+        assert_eq!((odd_even.size(), odd_even.depth()), (63, 10));
+        let pairwise = Network::pairwise(16);
+        assert_eq!((pairwise.size(), pairwise.depth()), (63, 10));
+
+        // 2 の冪乗でない長さでは、pairwise の方が比較器が少ないことがある
+        assert_eq!(Network::odd_even_merge(10).size(), 32);
+        assert_eq!(Network::pairwise(10).size(), 31);
+
+        assert_eq!(Network::bitonic(0).depth(), 0);
+        assert_eq!(Network::bitonic(1).size(), 0);
+    }
+
+    #[test]
+    fn from_comparators_packs_stages() {
+        // leaf の 4 要素のネットワークは 3 段
+        let network = Network::from_comparators(4, leaf::network(4));
+        assert_eq!(network.stages(), &[vec![(0, 2), (1, 3)], vec![(0, 1), (2, 3)], vec![(1, 2)]]);
+        assert_eq!(network.comparators().collect::<Vec<_>>(), leaf::network(4).collect::<Vec<_>>());
+        assert_eq!(network.n(), 4);
+    }
+
+    #[test]
+    fn apply_length_mismatch() {
+        let mut x = vec![3u32, 1, 2];
+        assert_eq!(Network::bitonic(4).apply(&mut x, &|a, b| a.cmp(b)), Err(SortError::InvalidLength { len: 3 }));
+    }
+
+    #[test]
+    fn parse_text_and_json() {
+        for n in 0..=20 {
+            for network in &[Network::bitonic(n), Network::odd_even_merge(n), Network::pairwise(n)] {
+                assert_eq!(Network::from_text(&network.to_text()).as_ref(), Ok(network));
+                assert_eq!(network.to_text().parse::<Network>().as_ref(), Ok(network));
+                assert_eq!(Network::from_json(&network.to_json()).as_ref(), Ok(network));
+            }
+        }
+
+        // 空白やキーの順序が違っても読める
+        let json = "{ \"stages\": [ [[1, 0], [2, 3]], [] ],\n  \"n\": 4 }";
+        assert_eq!(
+            Network::from_json(json),
+            Network::from_stages(4, vec![vec![(1, 0), (2, 3)], vec![]])
+        );
+        assert_eq!(Network::from_text("n=2\n0:1\n").map(|network| network.size()), Ok(1));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Network::from_text(""), Err(ParseError::MissingInputs));
+        assert_eq!(Network::from_text("4\n0:1"), Err(ParseError::MissingInputs));
+        assert_eq!(Network::from_text("n=x"), Err(ParseError::InvalidNumber { line: 1, column: 3 }));
+        assert_eq!(Network::from_text("n=4\n0:1 0-1"), Err(ParseError::InvalidComparator { line: 2, column: 5 }));
+        assert_eq!(Network::from_text("n=4\n\n0:a"), Err(ParseError::InvalidNumber { line: 3, column: 3 }));
+        assert_eq!(
+            Network::from_text("n=4\n0:4"),
+            Err(ParseError::OutOfRange { stage: 0, comparator: (0, 4), n: 4 })
+        );
+        assert_eq!(
+            Network::from_text("n=4\n0:1 1:2"),
+            Err(ParseError::Overlap { stage: 0, comparator: (1, 2) })
+        );
+        assert_eq!(Network::from_text("n=4\n2:2"), Err(ParseError::SameWire { stage: 0, comparator: (2, 2) }));
+        assert_eq!(
+            Network::from_text("n=4\n0:4").unwrap_err().to_string(),
+            "comparator (0, 4) in stage 0 is out of range for 4 inputs"
+        );
+
+        assert_eq!(Network::from_json("{\"n\":4}"), Err(ParseError::MissingField { name: "stages" }));
+        assert_eq!(
+            Network::from_json("{\"n\":4,\"stages\":[[[0,1,2]]]}"),
+            Err(ParseError::Syntax { line: 1, column: 23, expected: "']'" })
+        );
+        assert_eq!(
+            Network::from_json("{\"n\":4,\n\"stages\":[]} x"),
+            Err(ParseError::Syntax { line: 2, column: 14, expected: "end of input" })
+        );
+        assert!(matches!(Network::from_json("[1, 2"), Err(ParseError::Syntax { .. })));
+        assert!(matches!(Network::from_json("{\"n\":4,\"other\":1}"), Err(ParseError::Syntax { .. })));
+        assert!(matches!(Network::from_json("{\"n\":4,\"stages\":[[[0,\"1\"]]]}"), Err(ParseError::Syntax { .. })));
+        assert!(matches!(Network::from_json("{\"n\":4,\"stages\":[[[0,1]]}"), Err(ParseError::Syntax { .. })));
+        assert!(matches!(Network::from_json("{\"n\":4,\"stages\":[]\"\\q\":1}"), Err(ParseError::Syntax { .. })));
+
+        // 段 → 比較器より深い入れ子は再帰せずにエラーにする
+        let deep = format!("{{\"n\":4,\"stages\":{}{}}}", "[".repeat(100_000), "]".repeat(100_000));
+        assert!(matches!(Network::from_json(&deep), Err(ParseError::Syntax { .. })));
+    }
+
+    #[test]
+    fn parse_json_escapes() {
+        let expected = Network::from_stages(2, vec![vec![(0, 1)]]);
+        assert_eq!(Network::from_json("{\"\\u006e\":2,\"st\\u0061ges\":[[[0,1]]]}"), expected);
+        assert!(matches!(Network::from_json("{\"n\\\"\":2,\"stages\":[]}"), Err(ParseError::Syntax { .. })));
+        assert!(matches!(Network::from_json("{\"\\u00\":2}"), Err(ParseError::Syntax { .. })));
+        assert!(matches!(Network::from_json("{\"n"), Err(ParseError::Syntax { .. })));
+    }
+
+    #[test]
+    fn serialize() {
+        let network = Network::bitonic(4);
+        assert_eq!(network.to_text(), "n=4\n1:0 2:3\n0:2 1:3\n0:1 2:3\n");
+        assert_eq!(network.to_json(), "{\"n\":4,\"stages\":[[[1,0],[2,3]],[[0,2],[1,3]],[[0,1],[2,3]]]}");
+
+        assert_eq!(Network::bitonic(1).to_json(), "{\"n\":1,\"stages\":[]}");
+    }
+}
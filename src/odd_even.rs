@@ -0,0 +1,207 @@
+use super::{SortError, SortOrder};
+use crate::fourth::PARALLEL_THRESHOLD;
+use crate::sorter::{Capabilities, Sorter};
+use crate::network::{self, Network};
+use rayon::prelude::*;
+use std::cmp::Ordering;
+
+// Batcher の odd-even merge sort。比較器を Network として持たずに、段ごとにインデックスの範囲で実行する
+// (Network は要素数に比例以上のメモリを使うため)。`network::Network::odd_even_merge` は、ここで実行する比較器を記録したもの。
+//
+// 段 (p, k) では、長さ 2p のブロックの中で距離 k の組を比較する。
+// k == p の段はブロックの前半と後半を比較し、k < p の段はブロックの先頭と末尾の k 要素を除いた部分を
+// 長さ 2k ずつに区切って、それぞれの前半と後半を比較する。
+// 比較する組はすべて小さい方を前に置く向きなので、n 以上のインデックスとの組は飛ばすだけでよい。
+
+/// 長さ n のスライスに対して sort_by が実行する比較器を Network として取り出す
+pub fn network(n: usize) -> Network {
+    network::record(n, |x, comparator| {
+        sort_by(x, &comparator).expect("sort_by never fails");
+    })
+}
+
+pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    match *order {
+        SortOrder::Ascending  => sort_by(x, &|a, b| a.cmp(b)),
+        SortOrder::Descending => sort_by(x, &|a, b| b.cmp(a)),
+    }
+}
+
+pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+    where F: Fn(&T, &T) -> Ordering
+{
+    for (p, k) in stages(x.len()) {
+        for block in x.chunks_mut(2 * p) {
+            for chunk in inner(block, p, k).chunks_mut(2 * k) {
+                if chunk.len() > k {
+                    let (first, second) = chunk.split_at_mut(k);
+                    for (a, b) in first.iter_mut().zip(second.iter_mut()) {
+                        compare_and_swap(a, b, comparator);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 各段の中の比較を rayon で並列に実行する
+pub fn par_sort<T: Ord + Send>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    match *order {
+        SortOrder::Ascending  => par_sort_by(x, &|a, b| a.cmp(b)),
+        SortOrder::Descending => par_sort_by(x, &|a, b| b.cmp(a)),
+    }
+}
+
+pub fn par_sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+    where T: Send,
+        F: Sync + Fn(&T, &T) -> Ordering,
+{
+    for (p, k) in stages(x.len()) {
+        // ブロックや組の区間が小さい段では、1 タスクがまとめて処理する数を増やす
+        x.par_chunks_mut(2 * p)
+            .with_min_len((PARALLEL_THRESHOLD / (2 * p)).max(1))
+            .for_each(|block| {
+                inner(block, p, k).par_chunks_mut(2 * k)
+                    .with_min_len((PARALLEL_THRESHOLD / (2 * k)).max(1))
+                    .filter(|chunk| chunk.len() > k)
+                    .for_each(|chunk| {
+                        let (first, second) = chunk.split_at_mut(k);
+                        first.par_iter_mut()
+                            .zip(second.par_iter_mut())
+                            .with_min_len(PARALLEL_THRESHOLD)
+                            .for_each(|(a, b)| compare_and_swap(a, b, comparator));
+                    });
+            });
+    }
+    Ok(())
+}
+
+/// 長さ n のスライスをソートする段 (p, k) を、実行する順に返す
+fn stages(n: usize) -> Vec<(usize, usize)> {
+    let mut stages = Vec::new();
+    let mut p = 1;
+    while p < n {
+        let mut k = p;
+        while k > 0 {
+            stages.push((p, k));
+            k /= 2;
+        }
+        p *= 2;
+    }
+    stages
+}
+
+/// 長さ 2p のブロックのうち、段 (p, k) で比較する組が入っている区間
+fn inner<T>(block: &mut [T], p: usize, k: usize) -> &mut [T] {
+    if k == p {
+        block
+    } else {
+        // 先頭と末尾の k 要素は、ブロックをまたぐ組になるので比較しない
+        let end = block.len().min(2 * p - k);
+        let start = k.min(end);
+        &mut block[start..end]
+    }
+}
+
+fn compare_and_swap<T, F>(a: &mut T, b: &mut T, comparator: &F)
+    where F: Fn(&T, &T) -> Ordering
+{
+    if comparator(a, b) == Ordering::Greater {
+        std::mem::swap(a, b);
+    }
+}
+
+/// `Sorter` トレイトで odd_even の sort_by (odd-even merge sort) を呼び出すための型
+pub struct OddEven;
+
+impl<T: Ord> Sorter<T> for OddEven {
+    fn name(&self) -> &'static str {
+        "odd_even"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { parallel: false, generic: true, power_of_two_only: false, comparator: true }
+    }
+
+    fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+        sort(x, order)
+    }
+
+    fn sort_by(&self, x: &mut [T], comparator: &(dyn Fn(&T, &T) -> Ordering + Sync)) -> Result<(), SortError> {
+        sort_by(x, &|a: &T, b: &T| comparator(a, b))
+    }
+}
+
+/// `Sorter` トレイトで odd_even の par_sort_by を呼び出すための型
+pub struct ParOddEven;
+
+impl<T: Ord + Send> Sorter<T> for ParOddEven {
+    fn name(&self) -> &'static str {
+        "odd_even_par"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { parallel: true, generic: true, power_of_two_only: false, comparator: true }
+    }
+
+    fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+        par_sort(x, order)
+    }
+
+    fn sort_by(&self, x: &mut [T], comparator: &(dyn Fn(&T, &T) -> Ordering + Sync)) -> Result<(), SortError> {
+        par_sort_by(x, &|a: &T, b: &T| comparator(a, b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{par_sort, par_sort_by, sort, sort_by};
+    use crate::network::Network;
+    use crate::SortOrder::{Ascending as Asc, Descending as Desc};
+    use crate::utils::{is_sorted, new_u32_vec};
+
+    #[test]
+    fn sort_str_ascending() {
+        let mut x = vec!["Rust", "is", "fast", "and", "memory-efficient", "with", "no", "GC"];
+
+        assert_eq!(sort(&mut x, &Asc), Ok(()));
+
+        assert_eq!(x, vec!["GC", "Rust", "and", "fast", "is", "memory-efficient", "no", "with"]);
+    }
+
+    #[test]
+    fn same_comparators_as_network() {
+        // キーが重複していると、比較器の並びが違えば同じキーの要素の並び順が変わる
+        for n in 0..=100 {
+            let input: Vec<(u32, usize)> = new_u32_vec(n).into_iter().map(|v| v % 8).zip(0..).collect();
+            let comparator = |a: &(u32, usize), b: &(u32, usize)| b.0.cmp(&a.0);
+
+            let mut expected = input.clone();
+            assert_eq!(Network::odd_even_merge(n).apply(&mut expected, &comparator), Ok(()));
+
+            let mut x = input.clone();
+            assert_eq!(sort_by(&mut x, &comparator), Ok(()));
+            assert_eq!(x, expected, "n = {}", n);
+
+            let mut x = input;
+            assert_eq!(par_sort_by(&mut x, &comparator), Ok(()));
+            assert_eq!(x, expected, "n = {}", n);
+        }
+    }
+
+    #[test]
+    fn sort_u32_large() {
+        for &len in &[4096, 5000, 70000] {
+            for order in &[Asc, Desc] {
+                let mut x = new_u32_vec(len);
+                assert_eq!(sort(&mut x, order), Ok(()));
+                assert!(is_sorted(&x, order));
+
+                let mut x = new_u32_vec(len);
+                assert_eq!(par_sort(&mut x, order), Ok(()));
+                assert!(is_sorted(&x, order));
+            }
+        }
+    }
+}
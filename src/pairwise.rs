@@ -0,0 +1,281 @@
+use super::{SortError, SortOrder};
+use crate::fourth::PARALLEL_THRESHOLD;
+use crate::sorter::{Capabilities, Sorter};
+use crate::network::{self, Network};
+use rayon::prelude::*;
+use std::cmp::Ordering;
+
+// Parberry の pairwise sorting network。比較器を段ごとにインデックスの範囲で実行する。
+// `network::Network::pairwise` は、ここで実行する比較器を記録したもの。
+//
+// どの段も、x を長さ a のブロックに区切り、ブロック i と i + distance の同じ位置の要素同士を比較する。
+// 比較する側 (前) のブロックは偶数番目か奇数番目のどちらか一方だけで、distance は常に奇数なので、
+// 比較する側のブロックの先頭から長さ 2a ずつ区切ると、前半が比較する側、後半が相手側のブロックになる。
+// 相手はその (distance - 1) / 2 個後ろの組の後半にあるので、(distance - 1) * a ずつの領域を 2 つずつ組にして
+// 前の領域の前半と後ろの領域の後半を比較すれば、領域の組どうしは重ならない。
+// 組の始まりを 0 と (distance - 1) * a の 2 通りにずらして処理すれば、段の比較器をすべて実行できる。
+
+/// pairwise network の 1 段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Layer {
+    /// ブロックの長さ
+    a: usize,
+    /// 比較する側のブロックの偶奇 (0 なら偶数番目)
+    offset: usize,
+    /// 相手のブロックまでの距離 (ブロック数、奇数)
+    distance: usize,
+}
+
+/// 長さ n のスライスに対して sort_by が実行する比較器を Network として取り出す
+pub fn network(n: usize) -> Network {
+    network::record(n, |x, comparator| {
+        sort_by(x, &comparator).expect("sort_by never fails");
+    })
+}
+
+pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    match *order {
+        SortOrder::Ascending  => sort_by(x, &|a, b| a.cmp(b)),
+        SortOrder::Descending => sort_by(x, &|a, b| b.cmp(a)),
+    }
+}
+
+pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+    where F: Fn(&T, &T) -> Ordering
+{
+    for layer in layers(x.len()) {
+        let a = layer.a;
+        let (y, span) = shift_blocks(x, layer);
+        if span == 0 {
+            for chunk in y.chunks_mut(2 * a) {
+                let (first, second) = split_half(chunk, a);
+                compare_slices(first, second, comparator);
+            }
+            continue;
+        }
+        for &start in &[0, span] {
+            if start >= y.len() {
+                continue;
+            }
+            for chunk in y[start..].chunks_mut(2 * span) {
+                let (first, second) = split_half(chunk, span);
+                let lower = first.chunks_mut(2 * a).map(|block| split_half(block, a).0);
+                let upper = second.chunks_mut(2 * a).map(|block| split_half(block, a).1);
+                for (first, second) in lower.zip(upper) {
+                    compare_slices(first, second, comparator);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 各段の中の比較を rayon で並列に実行する
+pub fn par_sort<T: Ord + Send>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    match *order {
+        SortOrder::Ascending  => par_sort_by(x, &|a, b| a.cmp(b)),
+        SortOrder::Descending => par_sort_by(x, &|a, b| b.cmp(a)),
+    }
+}
+
+pub fn par_sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+    where T: Send,
+        F: Sync + Fn(&T, &T) -> Ordering,
+{
+    for layer in layers(x.len()) {
+        let a = layer.a;
+        // ブロックが小さい段では、1 タスクがまとめて処理するブロック数を増やす
+        let min_blocks = (PARALLEL_THRESHOLD / a).max(1);
+        let (y, span) = shift_blocks(x, layer);
+        if span == 0 {
+            y.par_chunks_mut(2 * a)
+                .with_min_len(min_blocks)
+                .for_each(|chunk| {
+                    let (first, second) = split_half(chunk, a);
+                    par_compare_slices(first, second, comparator);
+                });
+            continue;
+        }
+        for &start in &[0, span] {
+            if start >= y.len() {
+                continue;
+            }
+            y[start..].par_chunks_mut(2 * span).for_each(|chunk| {
+                let (first, second) = split_half(chunk, span);
+                let lower = first.par_chunks_mut(2 * a).map(|block| split_half(block, a).0);
+                let upper = second.par_chunks_mut(2 * a).map(|block| split_half(block, a).1);
+                lower.zip(upper)
+                    .with_min_len(min_blocks)
+                    .for_each(|(first, second)| par_compare_slices(first, second, comparator));
+            });
+        }
+    }
+    Ok(())
+}
+
+/// 長さ n のスライスをソートする段を、実行する順に返す
+fn layers(n: usize) -> Vec<Layer> {
+    let mut layers = Vec::new();
+
+    // 前半: 隣り合うブロックの組を比較しながら、ブロックの長さを倍にしていく
+    let mut a = 1;
+    while a < n {
+        layers.push(Layer { a, offset: 0, distance: 1 });
+        a *= 2;
+    }
+
+    // 後半: 奇数番目のブロックと、その distance 先のブロックを比較する
+    a /= 4;
+    let mut e = 1;
+    while a > 0 {
+        let mut d = e;
+        while d > 0 {
+            layers.push(Layer { a, offset: 1, distance: d });
+            d /= 2;
+        }
+        a /= 2;
+        e = e * 2 + 1;
+    }
+    layers
+}
+
+/// x の先頭から比較する側の最初のブロックの前までを除いたスライスと、組にする領域の長さ (distance - 1) * a を返す
+///
+/// 返したスライスを長さ 2a ずつ区切ると、前半が比較する側のブロック、後半が相手側のブロックになる
+fn shift_blocks<T>(x: &mut [T], layer: Layer) -> (&mut [T], usize) {
+    let start = (layer.offset * layer.a).min(x.len());
+    (&mut x[start..], (layer.distance - 1) * layer.a)
+}
+
+/// chunk を mid の位置で 2 つに分ける。chunk が mid より短ければ後半は空になる
+fn split_half<T>(chunk: &mut [T], mid: usize) -> (&mut [T], &mut [T]) {
+    let mid = mid.min(chunk.len());
+    chunk.split_at_mut(mid)
+}
+
+/// 2 つのブロックの同じ位置の要素同士を比較する。相手が n 以降にある要素は、zip で自然に飛ばされる
+fn compare_slices<T, F>(first: &mut [T], second: &mut [T], comparator: &F)
+    where F: Fn(&T, &T) -> Ordering
+{
+    for (a, b) in first.iter_mut().zip(second.iter_mut()) {
+        compare_and_swap(a, b, comparator);
+    }
+}
+
+fn par_compare_slices<T, F>(first: &mut [T], second: &mut [T], comparator: &F)
+    where T: Send,
+        F: Sync + Fn(&T, &T) -> Ordering,
+{
+    first.par_iter_mut()
+        .zip(second.par_iter_mut())
+        .with_min_len(PARALLEL_THRESHOLD)
+        .for_each(|(a, b)| compare_and_swap(a, b, comparator));
+}
+
+fn compare_and_swap<T, F>(a: &mut T, b: &mut T, comparator: &F)
+    where F: Fn(&T, &T) -> Ordering
+{
+    if comparator(a, b) == Ordering::Greater {
+        std::mem::swap(a, b);
+    }
+}
+
+/// `Sorter` トレイトで pairwise の sort_by (pairwise sorting network) を呼び出すための型
+pub struct Pairwise;
+
+impl<T: Ord> Sorter<T> for Pairwise {
+    fn name(&self) -> &'static str {
+        "pairwise"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { parallel: false, generic: true, power_of_two_only: false, comparator: true }
+    }
+
+    fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+        sort(x, order)
+    }
+
+    fn sort_by(&self, x: &mut [T], comparator: &(dyn Fn(&T, &T) -> Ordering + Sync)) -> Result<(), SortError> {
+        sort_by(x, &|a: &T, b: &T| comparator(a, b))
+    }
+}
+
+/// `Sorter` トレイトで pairwise の par_sort_by を呼び出すための型
+pub struct ParPairwise;
+
+impl<T: Ord + Send> Sorter<T> for ParPairwise {
+    fn name(&self) -> &'static str {
+        "pairwise_par"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { parallel: true, generic: true, power_of_two_only: false, comparator: true }
+    }
+
+    fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+        par_sort(x, order)
+    }
+
+    fn sort_by(&self, x: &mut [T], comparator: &(dyn Fn(&T, &T) -> Ordering + Sync)) -> Result<(), SortError> {
+        par_sort_by(x, &|a: &T, b: &T| comparator(a, b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{layers, par_sort, par_sort_by, sort, sort_by};
+    use crate::network::Network;
+    use crate::SortOrder::{Ascending as Asc, Descending as Desc};
+    use crate::utils::{is_sorted, new_u32_vec};
+
+    #[test]
+    fn sort_str_ascending() {
+        let mut x = vec!["Rust", "is", "fast", "and", "memory-efficient", "with", "no", "GC"];
+
+        assert_eq!(sort(&mut x, &Asc), Ok(()));
+
+        assert_eq!(x, vec!["GC", "Rust", "and", "fast", "is", "memory-efficient", "no", "with"]);
+    }
+
+    #[test]
+    fn depth_of_sixteen() {
+        assert_eq!(layers(16).len(), Network::pairwise(16).depth());
+        assert!(layers(1).is_empty());
+    }
+
+    #[test]
+    fn same_comparators_as_network() {
+        // キーが重複していると、比較器の並びが違えば同じキーの要素の並び順が変わる
+        for n in 0..=100 {
+            let input: Vec<(u32, usize)> = new_u32_vec(n).into_iter().map(|v| v % 8).zip(0..).collect();
+            let comparator = |a: &(u32, usize), b: &(u32, usize)| b.0.cmp(&a.0);
+
+            let mut expected = input.clone();
+            assert_eq!(Network::pairwise(n).apply(&mut expected, &comparator), Ok(()));
+
+            let mut x = input.clone();
+            assert_eq!(sort_by(&mut x, &comparator), Ok(()));
+            assert_eq!(x, expected, "n = {}", n);
+
+            let mut x = input;
+            assert_eq!(par_sort_by(&mut x, &comparator), Ok(()));
+            assert_eq!(x, expected, "n = {}", n);
+        }
+    }
+
+    #[test]
+    fn sort_u32_large() {
+        for &len in &[4096, 5000, 70000] {
+            for order in &[Asc, Desc] {
+                let mut x = new_u32_vec(len);
+                assert_eq!(sort(&mut x, order), Ok(()));
+                assert!(is_sorted(&x, order));
+
+                let mut x = new_u32_vec(len);
+                assert_eq!(par_sort(&mut x, order), Ok(()));
+                assert!(is_sorted(&x, order));
+            }
+        }
+    }
+}
@@ -1,11 +1,17 @@
-use super::SortOrder;
+use super::{SortError, SortOrder};
+use crate::sorter::{Capabilities, Sorter};
+use std::cmp::Ordering;
 
 
-pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) {
+pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    if x.len() > 1 && !x.len().is_power_of_two() {
+        return Err(SortError::InvalidLength { len: x.len() });
+    }
     match *order {
         SortOrder::Ascending =>  do_sort(x, true),
         SortOrder::Descending => do_sort(x, false),
     }
+    Ok(())
 }
 
 fn do_sort<T: Ord>(x: &mut [T], up: bool) {
@@ -43,16 +49,40 @@ fn compare_and_swap<T: Ord>(x: &mut[T], up: bool) {
     }
 }
 
+/// `Sorter` トレイトで second を呼び出すための型
+/// 
+/// second はジェネリクスに対応しているが、長さは 2 の冪乗に限られ、comparator も受け取らない
+pub struct Second;
+
+impl<T: Ord> Sorter<T> for Second {
+    fn name(&self) -> &'static str {
+        "second"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { parallel: false, generic: true, power_of_two_only: true, comparator: false }
+    }
+
+    fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+        sort(x, order)
+    }
+
+    fn sort_by(&self, _x: &mut [T], _comparator: &(dyn Fn(&T, &T) -> Ordering + Sync)) -> Result<(), SortError> {
+        Err(SortError::Unsupported { sorter: Sorter::<T>::name(self) })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::sort;
+    use crate::SortError;
     use crate::SortOrder::{Ascending as Asc, Descending as Desc};
 
     #[test]
     fn sort_u32_ascending() {
         let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
 
-        sort(&mut x, &Asc);
+        assert_eq!(sort(&mut x, &Asc), Ok(()));
 
         assert_eq!(x, vec![4, 10, 11, 20, 21, 30, 110, 330]);
     }
@@ -61,7 +91,7 @@ mod tests {
     fn sort_u32_descending() {
         let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
 
-        sort(&mut x, &Desc);
+        assert_eq!(sort(&mut x, &Desc), Ok(()));
 
         assert_eq!(x, vec![330, 110, 30, 21, 20, 11, 10, 4]);
     }
@@ -70,7 +100,7 @@ mod tests {
     fn sort_str_ascending() {
         let mut x = vec!["Rust", "is", "fast", "and", "memory-efficient", "with", "no", "GC"];
 
-        sort(&mut x, &Asc);
+        assert_eq!(sort(&mut x, &Asc), Ok(()));
 
         assert_eq!(x, vec!["GC", "Rust", "and", "fast", "is", "memory-efficient", "no", "with"]);
     }
@@ -79,11 +109,18 @@ mod tests {
     fn sort_str_descending() {
         let mut x = vec!["Rust", "is", "fast", "and", "memory-efficient", "with", "no", "GC"];
 
-        sort(&mut x, &Desc);
+        assert_eq!(sort(&mut x, &Desc), Ok(()));
 
         assert_eq!(x, vec!["with", "no", "memory-efficient", "is", "fast", "and","Rust", "GC"]);
     }
 
+    #[test]
+    fn sort_invalid_length() {
+        let mut x = vec![10, 30, 11];
+
+        assert_eq!(sort(&mut x, &Asc), Err(SortError::InvalidLength { len: 3 }));
+    }
+
     // コンパイラが型エラーを検出してくれる例
     // #[test]
     // fn sort_f64_mixed() {
@@ -0,0 +1,269 @@
+use super::{NanPolicy, SortError, SortOrder};
+use crate::float::{self, Float};
+use crate::third;
+use std::cmp::Ordering;
+
+// パーセンタイルを 1 つ読むためだけに配列全体をソートするのは無駄が多いので、
+// クイックセレクト (ピボットで 3 つに分割して、目的の位置を含む側だけを続けて分割する) で選ぶ。
+// 区間が小さくなった場合や、分割が偏り続けた場合は、残りの区間を bitonic sort (third) でまとめてソートする。
+// どの関数も、選んだ要素 x[n] の前には x[n] 以下、後ろには x[n] 以上の要素だけが並んだ状態で返る。
+
+/// この要素数以下の区間は分割をやめて、bitonic sort でソートする
+const SORT_THRESHOLD: usize = 32;
+
+/// comparator の順で n 番目 (0 始まり) の要素を x[n] に置き、その要素を返す
+///
+/// x[..n] には x[n] 以下、x[n + 1..] には x[n] 以上の要素が (順不同で) 並ぶ。
+/// n >= x.len() の場合は `SortError::InvalidLength` を返す
+pub fn select_nth<'a, T, F>(x: &'a mut [T], n: usize, comparator: &F) -> Result<&'a T, SortError>
+    where F: Fn(&T, &T) -> Ordering
+{
+    if n >= x.len() {
+        return Err(SortError::InvalidLength { len: x.len() });
+    }
+    select(x, n, comparator)?;
+    Ok(&x[n])
+}
+
+/// 中央値を返す。要素数が偶数の場合は小さい方 (x.len() / 2 - 1 番目) を返す
+///
+/// 空のスライスの場合は `SortError::InvalidLength` を返す
+pub fn median<T: Ord>(x: &mut [T]) -> Result<&T, SortError> {
+    median_by(x, &|a, b| a.cmp(b))
+}
+
+pub fn median_by<'a, T, F>(x: &'a mut [T], comparator: &F) -> Result<&'a T, SortError>
+    where F: Fn(&T, &T) -> Ordering
+{
+    if x.is_empty() {
+        return Err(SortError::InvalidLength { len: 0 });
+    }
+    let n = (x.len() - 1) / 2;
+    select_nth(x, n, comparator)
+}
+
+/// qs のそれぞれの分位点 (0.0 なら最小値、1.0 なら最大値) の要素を、qs と同じ順で返す
+///
+/// 分位点 q の要素は、ソートしたときに round(q * (x.len() - 1)) 番目に来る要素 (補間はしない)。
+/// 選んだ要素はそれぞれ、その位置で x を分割した状態になる。
+/// 0.0..=1.0 に入らない分位点があれば `SortError::InvalidQuantile`、空のスライスの場合は `SortError::InvalidLength` を返す
+pub fn quantiles<T: Ord + Clone>(x: &mut [T], qs: &[f64]) -> Result<Vec<T>, SortError> {
+    quantiles_by(x, qs, &|a, b| a.cmp(b))
+}
+
+pub fn quantiles_by<T, F>(x: &mut [T], qs: &[f64], comparator: &F) -> Result<Vec<T>, SortError>
+    where T: Clone,
+        F: Fn(&T, &T) -> Ordering,
+{
+    let indices = quantile_indices(x.len(), qs)?;
+
+    // 小さい位置から順に選ぶ。1 つ選ぶと、それより前の要素は以降の選択に関係しなくなる
+    let mut sorted = indices.clone();
+    sorted.sort_unstable();
+    sorted.dedup();
+    let mut lo = 0;
+    for &i in sorted.iter() {
+        select(&mut x[lo..], i - lo, comparator)?;
+        lo = i + 1;
+    }
+
+    Ok(indices.iter().map(|&i| x[i].clone()).collect())
+}
+
+/// 浮動小数点数の n 番目 (昇順、0 始まり) の要素を返す。NaN の扱いは policy に従う
+pub fn select_nth_floats<T: Float>(x: &mut [T], n: usize, policy: NanPolicy) -> Result<T, SortError> {
+    float::check_nan(x, &|v: &T| *v, policy)?;
+    select_nth(x, n, &float::comparator(&SortOrder::Ascending, policy)).copied()
+}
+
+/// 浮動小数点数の中央値を返す。要素数が偶数の場合は小さい方を返す (平均はとらない)
+pub fn median_floats<T: Float>(x: &mut [T], policy: NanPolicy) -> Result<T, SortError> {
+    float::check_nan(x, &|v: &T| *v, policy)?;
+    median_by(x, &float::comparator(&SortOrder::Ascending, policy)).copied()
+}
+
+/// 浮動小数点数の分位点の要素を返す。NaN の扱いは policy に従う
+pub fn quantiles_floats<T: Float>(x: &mut [T], qs: &[f64], policy: NanPolicy) -> Result<Vec<T>, SortError> {
+    float::check_nan(x, &|v: &T| *v, policy)?;
+    quantiles_by(x, qs, &float::comparator(&SortOrder::Ascending, policy))
+}
+
+/// 分位点を、ソートしたときのインデックスに変換する
+fn quantile_indices(len: usize, qs: &[f64]) -> Result<Vec<usize>, SortError> {
+    if len == 0 {
+        return Err(SortError::InvalidLength { len });
+    }
+    qs.iter()
+        .enumerate()
+        .map(|(index, &q)| {
+            if (0.0..=1.0).contains(&q) {
+                Ok((q * (len - 1) as f64).round() as usize)
+            } else {
+                Err(SortError::InvalidQuantile { index })
+            }
+        })
+        .collect()
+}
+
+/// x を分割して、comparator の順で n 番目の要素を x[n] に置く (n < x.len())
+fn select<T, F>(x: &mut [T], n: usize, comparator: &F) -> Result<(), SortError>
+    where F: Fn(&T, &T) -> Ordering
+{
+    let mut lo = 0;
+    let mut hi = x.len();
+    // 分割の回数の上限。ピボットの選び方が悪くて偏った分割が続いた場合も、
+    // 残りを bitonic sort に任せるので O(n log^2 n) を超えない
+    let mut budget = 2 * (usize::BITS - x.len().leading_zeros());
+
+    loop {
+        let range = &mut x[lo..hi];
+        if range.len() <= SORT_THRESHOLD || budget == 0 {
+            return third::sort_by(range, comparator);
+        }
+        budget -= 1;
+
+        let pivot = median_of_three(range, comparator);
+        let (lt, gt) = partition(range, pivot, comparator);
+        let k = n - lo;
+        if k < lt {
+            hi = lo + lt;
+        } else if k >= gt {
+            lo += gt;
+        } else {
+            // ピボットと等しい区間に入っていれば、そこで確定
+            return Ok(());
+        }
+    }
+}
+
+/// 先頭・中央・末尾の 3 つのうち、真ん中の値のインデックス
+fn median_of_three<T, F>(x: &[T], comparator: &F) -> usize
+    where F: Fn(&T, &T) -> Ordering
+{
+    let (a, b, c) = (0, x.len() / 2, x.len() - 1);
+    let less = |i: usize, j: usize| comparator(&x[i], &x[j]) == Ordering::Less;
+
+    if less(a, b) {
+        if less(b, c) { b } else if less(a, c) { c } else { a }
+    } else if less(a, c) {
+        a
+    } else if less(b, c) {
+        c
+    } else {
+        b
+    }
+}
+
+/// x[pivot] で x を 3 つに分割する (Dijkstra の 3-way partition)
+///
+/// 戻り値を (lt, gt) とすると、x[..lt] はピボットより小さく、x[lt..gt] はピボットと等しく、x[gt..] はピボットより大きい。
+/// 等しい要素を 1 か所に集めるので、重複の多い入力でも分割が偏らない
+fn partition<T, F>(x: &mut [T], pivot: usize, comparator: &F) -> (usize, usize)
+    where F: Fn(&T, &T) -> Ordering
+{
+    x.swap(0, pivot);
+    // x[lt] には常にピボットと等しい要素がある
+    let mut lt = 0;
+    let mut i = 1;
+    let mut gt = x.len();
+
+    while i < gt {
+        match comparator(&x[i], &x[lt]) {
+            Ordering::Less => {
+                x.swap(lt, i);
+                lt += 1;
+                i += 1;
+            },
+            Ordering::Greater => {
+                gt -= 1;
+                x.swap(i, gt);
+            },
+            Ordering::Equal => i += 1,
+        }
+    }
+    (lt, gt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{median, median_floats, quantiles, quantiles_floats, select_nth, select_nth_floats};
+    use crate::{NanPolicy, SortError};
+    use crate::utils::new_u32_vec;
+
+    #[test]
+    fn select_nth_partitions_around_nth() {
+        for &len in &[1, 2, 3, 33, 100, 1000, 10000] {
+            let original = new_u32_vec(len);
+            // 重複の多い入力も試す
+            let duplicated: Vec<u32> = original.iter().map(|v| v % 4).collect();
+
+            for input in &[original, duplicated] {
+                let mut expected = input.clone();
+                expected.sort();
+
+                for &n in &[0, len / 3, len / 2, len - 1] {
+                    let mut x = input.clone();
+                    assert_eq!(select_nth(&mut x, n, &|a, b| a.cmp(b)), Ok(&expected[n]));
+                    assert!(x[..n].iter().all(|v| *v <= x[n]), "len = {}, n = {}", len, n);
+                    assert!(x[n + 1..].iter().all(|v| *v >= x[n]), "len = {}, n = {}", len, n);
+
+                    x.sort();
+                    assert_eq!(x, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn select_nth_sorted_input() {
+        // ソート済み・逆順の入力でも分割が偏らない (偏っても bitonic sort に切り替わる)
+        let mut x: Vec<u32> = (0..10000).collect();
+        assert_eq!(select_nth(&mut x, 1234, &|a, b| a.cmp(b)), Ok(&1234));
+        assert_eq!(select_nth(&mut x, 1234, &|a, b| b.cmp(a)), Ok(&(9999 - 1234)));
+    }
+
+    #[test]
+    fn select_nth_out_of_range() {
+        let mut x = vec![3u32, 1, 2];
+        assert_eq!(select_nth(&mut x, 3, &|a, b| a.cmp(b)), Err(SortError::InvalidLength { len: 3 }));
+        assert_eq!(median(&mut Vec::<u32>::new()), Err(SortError::InvalidLength { len: 0 }));
+    }
+
+    #[test]
+    fn median_str() {
+        let mut x = vec!["Rust", "is", "fast", "and", "memory-efficient", "with", "no", "GC"];
+        assert_eq!(median(&mut x), Ok(&"fast"));
+
+        let mut x = vec![5u32, 1, 4, 2, 3];
+        assert_eq!(median(&mut x), Ok(&3));
+    }
+
+    #[test]
+    fn quantiles_u32() {
+        let mut x: Vec<u32> = (0..101).rev().collect();
+        assert_eq!(quantiles(&mut x, &[0.5, 0.0, 1.0, 0.25, 0.99]), Ok(vec![50, 0, 100, 25, 99]));
+        // 選んだ位置ごとに分割されている
+        assert_eq!(x[0], 0);
+        assert!(x[..25].iter().all(|&v| v < 25));
+        assert!(x[26..50].iter().all(|&v| (26..50).contains(&v)));
+
+        assert_eq!(quantiles(&mut x, &[0.5, 1.5]), Err(SortError::InvalidQuantile { index: 1 }));
+        assert_eq!(quantiles(&mut x, &[f64::NAN]), Err(SortError::InvalidQuantile { index: 0 }));
+        assert_eq!(quantiles(&mut Vec::<u32>::new(), &[0.5]), Err(SortError::InvalidLength { len: 0 }));
+    }
+
+    #[test]
+    fn floats_with_nan_policy() {
+        let x = [2.5, f64::NAN, -1.0, 0.0, 10.0];
+
+        assert_eq!(median_floats(&mut x.clone(), NanPolicy::NanLast), Ok(2.5));
+        assert_eq!(median_floats(&mut x.clone(), NanPolicy::NanFirst), Ok(0.0));
+        assert_eq!(median_floats(&mut x.clone(), NanPolicy::Error), Err(SortError::NaN { index: 1 }));
+
+        assert_eq!(select_nth_floats(&mut x.clone(), 0, NanPolicy::NanLast), Ok(-1.0));
+        assert!(select_nth_floats(&mut x.clone(), 4, NanPolicy::NanLast).unwrap().is_nan());
+
+        assert_eq!(quantiles_floats(&mut x.clone(), &[0.0, 0.75], NanPolicy::NanLast), Ok(vec![-1.0, 10.0]));
+        assert_eq!(quantiles_floats(&mut [3.0f32, 1.0, 2.0], &[0.5], NanPolicy::Error), Ok(vec![2.0]));
+    }
+}
@@ -0,0 +1,593 @@
+use super::{SortError, SortOrder};
+use crate::iterative;
+use crate::sorter::{Capabilities, Sorter};
+use std::cmp::Ordering;
+
+// u32, i32, f32 専用の SIMD 版 bitonic sort (cargo の feature "simd" を有効にしたときだけビルドされる)。
+//
+// iterative と同じ「比較する組をすべて昇順に揃えた」bitonic network を使い、
+// 比較・交換をまとめて min/max 命令で行う。
+//
+// - AVX2: 8 要素を 1 レジスタに載せ、8 / 16 要素のブロックはレジスタ内の network (permute + min/max + blend) でソートし、
+//   それより大きい段は 8 要素ずつ compare_and_swap する
+// - SSE4.1: 4 要素を 1 レジスタに載せ、4 / 8 要素のブロックはレジスタ内の network (shuffle + min/max + blend) でソートし、
+//   それより大きい段は 4 要素ずつ compare_and_swap する
+// - どちらも使えない CPU (または x86 以外) では iterative のスカラー実装にフォールバックする
+//
+// どの CPU 拡張を使うかは実行時に is_x86_feature_detected! で判定する。
+// 長さ n が 2 の冪乗でない場合は、iterative と同じく n 以上のインデックスに +∞ が並んでいるとみなす。
+// レジスタに収まる組はそのまま min/max で処理し、n をまたぐレジスタの組だけをスカラーで比較するので、
+// 作業用のバッファは確保しない。
+
+/// SIMD で処理するのに必要な最小の要素数。これより短いとスカラー実装を使う
+const MIN_SIMD_LEN: usize = 16;
+
+pub fn sort_u32(x: &mut [u32], order: &SortOrder) -> Result<(), SortError> {
+    sort_u32_ascending(x);
+    finish(x, order)
+}
+
+pub fn sort_i32(x: &mut [i32], order: &SortOrder) -> Result<(), SortError> {
+    sort_i32_ascending(x);
+    finish(x, order)
+}
+
+/// f32 をソートする
+///
+/// NaN を含む場合は min/max 命令では正しく並べられないので、`f32::total_cmp` の順序で
+/// スカラー実装を使ってソートする (正の NaN は末尾、負の NaN は先頭に来る)
+pub fn sort_f32(x: &mut [f32], order: &SortOrder) -> Result<(), SortError> {
+    if x.iter().any(|v| v.is_nan()) {
+        iterative::sort_by(x, &|a: &f32, b: &f32| a.total_cmp(b))?;
+    } else {
+        sort_f32_ascending(x);
+    }
+    finish(x, order)
+}
+
+/// 昇順にソート済みの x を order に合わせて並べ替える
+fn finish<T>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    if let SortOrder::Descending = *order {
+        x.reverse();
+    }
+    Ok(())
+}
+
+macro_rules! define_ascending_sort {
+    ($name:ident, $t:ty, $x86_mod:ident, $comparator:expr) => {
+        fn $name(x: &mut [$t]) {
+            if x.len() < MIN_SIMD_LEN {
+                scalar_sort(x, &$comparator);
+                return;
+            }
+
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            {
+                if is_x86_feature_detected!("avx2") {
+                    // 実行中の CPU が AVX2 に対応していることを確認済み
+                    unsafe { x86::$x86_mod::sort_avx2(x) };
+                    return;
+                }
+                if is_x86_feature_detected!("sse4.1") {
+                    // 実行中の CPU が SSE4.1 に対応していることを確認済み
+                    unsafe { x86::$x86_mod::sort_sse41(x) };
+                    return;
+                }
+            }
+
+            scalar_sort(x, &$comparator);
+        }
+    };
+}
+
+define_ascending_sort!(sort_u32_ascending, u32, u32x, |a: &u32, b: &u32| a.cmp(b));
+define_ascending_sort!(sort_i32_ascending, i32, i32x, |a: &i32, b: &i32| a.cmp(b));
+// NaN は呼び出し前に取り除いているので partial_cmp は必ず Some を返す
+define_ascending_sort!(sort_f32_ascending, f32, f32x, |a: &f32, b: &f32| a.partial_cmp(b).unwrap());
+
+fn scalar_sort<T, F>(x: &mut [T], comparator: &F)
+    where F: Fn(&T, &T) -> std::cmp::Ordering
+{
+    for stage in iterative::stages(x.len()) {
+        iterative::run_stage(x, stage, comparator);
+    }
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86 {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::*;
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::*;
+
+    // 型ごとの min/max 命令。レジスタはすべて整数型 (__m128i / __m256i) で受け渡し、f32 だけ中でキャストする。
+    //
+    // f32 の min/max 命令は、2 つの値が等しい (-0.0 と +0.0 など) とき 2 番目の引数を返す。
+    // 2 つのレジスタ a, b を比較する場合に min(a, b), max(a, b) とすると両方 b になって a が失われるので、
+    // 呼び出し側では min(a, b), max(b, a) の順で使うこと。
+
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn min_u32x8(a: __m256i, b: __m256i) -> __m256i { _mm256_min_epu32(a, b) }
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn max_u32x8(a: __m256i, b: __m256i) -> __m256i { _mm256_max_epu32(a, b) }
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn min_i32x8(a: __m256i, b: __m256i) -> __m256i { _mm256_min_epi32(a, b) }
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn max_i32x8(a: __m256i, b: __m256i) -> __m256i { _mm256_max_epi32(a, b) }
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn min_f32x8(a: __m256i, b: __m256i) -> __m256i {
+        _mm256_castps_si256(_mm256_min_ps(_mm256_castsi256_ps(a), _mm256_castsi256_ps(b)))
+    }
+    #[inline]
+    #[target_feature(enable = "avx2")]
+    unsafe fn max_f32x8(a: __m256i, b: __m256i) -> __m256i {
+        _mm256_castps_si256(_mm256_max_ps(_mm256_castsi256_ps(a), _mm256_castsi256_ps(b)))
+    }
+
+    #[inline]
+    #[target_feature(enable = "sse4.1")]
+    unsafe fn min_u32x4(a: __m128i, b: __m128i) -> __m128i { _mm_min_epu32(a, b) }
+    #[inline]
+    #[target_feature(enable = "sse4.1")]
+    unsafe fn max_u32x4(a: __m128i, b: __m128i) -> __m128i { _mm_max_epu32(a, b) }
+    #[inline]
+    #[target_feature(enable = "sse4.1")]
+    unsafe fn min_i32x4(a: __m128i, b: __m128i) -> __m128i { _mm_min_epi32(a, b) }
+    #[inline]
+    #[target_feature(enable = "sse4.1")]
+    unsafe fn max_i32x4(a: __m128i, b: __m128i) -> __m128i { _mm_max_epi32(a, b) }
+    #[inline]
+    #[target_feature(enable = "sse4.1")]
+    unsafe fn min_f32x4(a: __m128i, b: __m128i) -> __m128i {
+        _mm_castps_si128(_mm_min_ps(_mm_castsi128_ps(a), _mm_castsi128_ps(b)))
+    }
+    #[inline]
+    #[target_feature(enable = "sse4.1")]
+    unsafe fn max_f32x4(a: __m128i, b: __m128i) -> __m128i {
+        _mm_castps_si128(_mm_max_ps(_mm_castsi128_ps(a), _mm_castsi128_ps(b)))
+    }
+
+    macro_rules! define_kernels {
+        ($mod_name:ident, $t:ty, $min8:ident, $max8:ident, $min4:ident, $max4:ident, $comparator:expr) => {
+            pub(super) mod $mod_name {
+                use super::*;
+                use crate::iterative;
+
+                /// 8 要素のレジスタ内で、lane i と lane idx[i] を比較・交換する 1 段
+                ///
+                /// HIGH は組のうちインデックスが大きい側 (最大値を受け取る側) の lane を表すビットマスク
+                #[inline]
+                #[target_feature(enable = "avx2")]
+                unsafe fn stage8<const HIGH: i32>(v: __m256i, idx: __m256i) -> __m256i {
+                    let p = _mm256_permutevar8x32_epi32(v, idx);
+                    // lane i と lane idx[i] で引数の順序が入れ替わるので、等しい値が重複することはない
+                    let lo = $min8(v, p);
+                    let hi = $max8(v, p);
+                    _mm256_blend_epi32::<HIGH>(lo, hi)
+                }
+
+                #[inline]
+                #[target_feature(enable = "avx2")]
+                unsafe fn reverse8(v: __m256i) -> __m256i {
+                    _mm256_permutevar8x32_epi32(v, _mm256_setr_epi32(7, 6, 5, 4, 3, 2, 1, 0))
+                }
+
+                /// bitonic 列になっている 8 要素を昇順にする (比較距離 4, 2, 1 の段)
+                #[inline]
+                #[target_feature(enable = "avx2")]
+                unsafe fn clean8(v: __m256i) -> __m256i {
+                    let v = stage8::<0b1111_0000>(v, _mm256_setr_epi32(4, 5, 6, 7, 0, 1, 2, 3));
+                    let v = stage8::<0b1100_1100>(v, _mm256_setr_epi32(2, 3, 0, 1, 6, 7, 4, 5));
+                    stage8::<0b1010_1010>(v, _mm256_setr_epi32(1, 0, 3, 2, 5, 4, 7, 6))
+                }
+
+                /// 8 要素をレジスタ内でソートする
+                #[inline]
+                #[target_feature(enable = "avx2")]
+                unsafe fn sort8(v: __m256i) -> __m256i {
+                    // k = 2
+                    let v = stage8::<0b1010_1010>(v, _mm256_setr_epi32(1, 0, 3, 2, 5, 4, 7, 6));
+                    // k = 4
+                    let v = stage8::<0b1100_1100>(v, _mm256_setr_epi32(3, 2, 1, 0, 7, 6, 5, 4));
+                    let v = stage8::<0b1010_1010>(v, _mm256_setr_epi32(1, 0, 3, 2, 5, 4, 7, 6));
+                    // k = 8
+                    let v = stage8::<0b1111_0000>(v, _mm256_setr_epi32(7, 6, 5, 4, 3, 2, 1, 0));
+                    let v = stage8::<0b1100_1100>(v, _mm256_setr_epi32(2, 3, 0, 1, 6, 7, 4, 5));
+                    stage8::<0b1010_1010>(v, _mm256_setr_epi32(1, 0, 3, 2, 5, 4, 7, 6))
+                }
+
+                /// a の lane i と b の lane 7 - i を比較して、小さい方を 1 つ目、大きい方を 2 つ目のレジスタに集める
+                #[inline]
+                #[target_feature(enable = "avx2")]
+                unsafe fn flip8(a: __m256i, b: __m256i) -> (__m256i, __m256i) {
+                    let b = reverse8(b);
+                    ($min8(a, b), reverse8($max8(b, a)))
+                }
+
+                /// 4 要素のレジスタ内で、lane i と lane IDX が指す lane を比較・交換する 1 段
+                ///
+                /// IDX は _mm_shuffle_epi32 の即値、HIGH は組のうち大きい値を受け取る lane を 16 ビット単位で表したマスク
+                #[inline]
+                #[target_feature(enable = "sse4.1")]
+                unsafe fn stage4<const IDX: i32, const HIGH: i32>(v: __m128i) -> __m128i {
+                    let p = _mm_shuffle_epi32::<IDX>(v);
+                    let lo = $min4(v, p);
+                    let hi = $max4(v, p);
+                    _mm_blend_epi16::<HIGH>(lo, hi)
+                }
+
+                #[inline]
+                #[target_feature(enable = "sse4.1")]
+                unsafe fn reverse4(v: __m128i) -> __m128i {
+                    _mm_shuffle_epi32::<0b00_01_10_11>(v)
+                }
+
+                /// bitonic 列になっている 4 要素を昇順にする (比較距離 2, 1 の段)
+                #[inline]
+                #[target_feature(enable = "sse4.1")]
+                unsafe fn clean4(v: __m128i) -> __m128i {
+                    let v = stage4::<0b01_00_11_10, 0b1111_0000>(v);
+                    stage4::<0b10_11_00_01, 0b1100_1100>(v)
+                }
+
+                /// 4 要素をレジスタ内でソートする
+                #[inline]
+                #[target_feature(enable = "sse4.1")]
+                unsafe fn sort4(v: __m128i) -> __m128i {
+                    // k = 2
+                    let v = stage4::<0b10_11_00_01, 0b1100_1100>(v);
+                    // k = 4
+                    let v = stage4::<0b00_01_10_11, 0b1111_0000>(v);
+                    stage4::<0b10_11_00_01, 0b1100_1100>(v)
+                }
+
+                /// a の lane i と b の lane 3 - i を比較して、小さい方を 1 つ目、大きい方を 2 つ目のレジスタに集める
+                #[inline]
+                #[target_feature(enable = "sse4.1")]
+                unsafe fn flip4(a: __m128i, b: __m128i) -> (__m128i, __m128i) {
+                    let b = reverse4(b);
+                    ($min4(a, b), reverse4($max4(b, a)))
+                }
+
+                /// p[i] と p[l] を比較して、順序が逆なら入れ替える。l が n 以上なら +∞ とみなして何もしない
+                ///
+                /// n をまたぐレジスタの分だけをスカラーで処理するのに使う。
+                /// レジスタの load / store と同じポインタを通して読み書きする (スライスを借用し直すとポインタが無効になる)
+                ///
+                /// # Safety
+                ///
+                /// p から n 要素が読み書きでき、i < l であること
+                #[inline]
+                unsafe fn compare_and_swap(p: *mut $t, n: usize, i: usize, l: usize) {
+                    if l < n && ($comparator)(&*p.add(i), &*p.add(l)) == std::cmp::Ordering::Greater {
+                        std::ptr::swap(p.add(i), p.add(l));
+                    }
+                }
+
+                /// p[i..] の width 要素 (n をまたいでよい) のブロックをスカラーでソートする
+                unsafe fn sort_block(p: *mut $t, n: usize, i: usize, width: usize) {
+                    for stage in iterative::stages(width) {
+                        for t in 0..width {
+                            let l = stage.partner(t);
+                            if t < l {
+                                compare_and_swap(p, n, i + t, i + l);
+                            }
+                        }
+                    }
+                }
+
+                /// p[i..] の width 要素 (n をまたいでよい) の bitonic 列をスカラーで昇順にする
+                unsafe fn clean_block(p: *mut $t, n: usize, i: usize, width: usize) {
+                    let mut j = width / 2;
+                    while j > 0 {
+                        for t in (0..width).filter(|t| t & j == 0) {
+                            compare_and_swap(p, n, i + t, i + t + j);
+                        }
+                        j /= 2;
+                    }
+                }
+
+                /// # Safety
+                ///
+                /// AVX2 に対応した CPU で呼ぶこと。長さは任意
+                #[target_feature(enable = "avx2")]
+                pub(in crate::simd) unsafe fn sort_avx2(x: &mut [$t]) {
+                    let n = x.len();
+                    let size = n.next_power_of_two();
+                    let p = x.as_mut_ptr();
+                    let load = |i: usize| _mm256_loadu_si256(p.add(i) as *const __m256i);
+                    let store = |i: usize, v: __m256i| _mm256_storeu_si256(p.add(i) as *mut __m256i, v);
+
+                    // 16 要素のブロックをレジスタ 2 本の中でソートする
+                    for i in (0..n).step_by(16) {
+                        if i + 16 <= n {
+                            let (a, b) = flip8(sort8(load(i)), sort8(load(i + 8)));
+                            store(i, clean8(a));
+                            store(i + 8, clean8(b));
+                        } else {
+                            sort_block(p, n, i, 16);
+                        }
+                    }
+
+                    let mut k = 32;
+                    while k <= size {
+                        // ブロックの前半と、反転した後半を比較する段
+                        for block in (0..n).step_by(k) {
+                            for i in (0..k / 2).step_by(8) {
+                                let lo = block + i;
+                                let hi = block + k - 8 - i;
+                                if hi + 8 <= n {
+                                    let (a, b) = flip8(load(lo), load(hi));
+                                    store(lo, a);
+                                    store(hi, b);
+                                } else {
+                                    for t in 0..8 {
+                                        compare_and_swap(p, n, lo + t, hi + 7 - t);
+                                    }
+                                }
+                            }
+                        }
+                        // 比較距離が 8 以上の half-cleaner はレジスタ単位で compare_and_swap する
+                        let mut j = k / 4;
+                        while j >= 8 {
+                            for block in (0..n).step_by(2 * j) {
+                                for lo in (block..block + j).step_by(8) {
+                                    if lo + j + 8 <= n {
+                                        let a = load(lo);
+                                        let b = load(lo + j);
+                                        store(lo, $min8(a, b));
+                                        store(lo + j, $max8(b, a));
+                                    } else {
+                                        for t in 0..8 {
+                                            compare_and_swap(p, n, lo + t, lo + j + t);
+                                        }
+                                    }
+                                }
+                            }
+                            j /= 2;
+                        }
+                        // 比較距離 4, 2, 1 はレジスタ内で済ませる
+                        for i in (0..n).step_by(8) {
+                            if i + 8 <= n {
+                                store(i, clean8(load(i)));
+                            } else {
+                                clean_block(p, n, i, 8);
+                            }
+                        }
+                        k *= 2;
+                    }
+                }
+
+                /// # Safety
+                ///
+                /// SSE4.1 に対応した CPU で呼ぶこと。長さは任意
+                #[target_feature(enable = "sse4.1")]
+                pub(in crate::simd) unsafe fn sort_sse41(x: &mut [$t]) {
+                    let n = x.len();
+                    let size = n.next_power_of_two();
+                    let p = x.as_mut_ptr();
+                    let load = |i: usize| _mm_loadu_si128(p.add(i) as *const __m128i);
+                    let store = |i: usize, v: __m128i| _mm_storeu_si128(p.add(i) as *mut __m128i, v);
+
+                    // 8 要素のブロックをレジスタ 2 本の中でソートする
+                    for i in (0..n).step_by(8) {
+                        if i + 8 <= n {
+                            let (a, b) = flip4(sort4(load(i)), sort4(load(i + 4)));
+                            store(i, clean4(a));
+                            store(i + 4, clean4(b));
+                        } else {
+                            sort_block(p, n, i, 8);
+                        }
+                    }
+
+                    let mut k = 16;
+                    while k <= size {
+                        // ブロックの前半と、反転した後半を比較する段
+                        for block in (0..n).step_by(k) {
+                            for i in (0..k / 2).step_by(4) {
+                                let lo = block + i;
+                                let hi = block + k - 4 - i;
+                                if hi + 4 <= n {
+                                    let (a, b) = flip4(load(lo), load(hi));
+                                    store(lo, a);
+                                    store(hi, b);
+                                } else {
+                                    for t in 0..4 {
+                                        compare_and_swap(p, n, lo + t, hi + 3 - t);
+                                    }
+                                }
+                            }
+                        }
+                        // 比較距離が 4 以上の half-cleaner はレジスタ単位で compare_and_swap する
+                        let mut j = k / 4;
+                        while j >= 4 {
+                            for block in (0..n).step_by(2 * j) {
+                                for lo in (block..block + j).step_by(4) {
+                                    if lo + j + 4 <= n {
+                                        let a = load(lo);
+                                        let b = load(lo + j);
+                                        store(lo, $min4(a, b));
+                                        store(lo + j, $max4(b, a));
+                                    } else {
+                                        for t in 0..4 {
+                                            compare_and_swap(p, n, lo + t, lo + j + t);
+                                        }
+                                    }
+                                }
+                            }
+                            j /= 2;
+                        }
+                        // 比較距離 2, 1 はレジスタ内で済ませる
+                        for i in (0..n).step_by(4) {
+                            if i + 4 <= n {
+                                store(i, clean4(load(i)));
+                            } else {
+                                clean_block(p, n, i, 4);
+                            }
+                        }
+                        k *= 2;
+                    }
+                }
+            }
+        };
+    }
+
+    define_kernels!(u32x, u32, min_u32x8, max_u32x8, min_u32x4, max_u32x4, |a: &u32, b: &u32| a.cmp(b));
+    define_kernels!(i32x, i32, min_i32x8, max_i32x8, min_i32x4, max_i32x4, |a: &i32, b: &i32| a.cmp(b));
+    define_kernels!(f32x, f32, min_f32x8, max_f32x8, min_f32x4, max_f32x4, |a: &f32, b: &f32| a.partial_cmp(b).unwrap());
+}
+
+/// `Sorter` トレイトで sort_u32 を呼び出すための型
+/// 
+/// SIMD の min/max 命令で比較するので、comparator は受け取らない
+pub struct Simd;
+
+impl Sorter<u32> for Simd {
+    fn name(&self) -> &'static str {
+        "simd"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { parallel: false, generic: false, power_of_two_only: false, comparator: false }
+    }
+
+    fn sort(&self, x: &mut [u32], order: &SortOrder) -> Result<(), SortError> {
+        sort_u32(x, order)
+    }
+
+    fn sort_by(&self, _x: &mut [u32], _comparator: &(dyn Fn(&u32, &u32) -> Ordering + Sync)) -> Result<(), SortError> {
+        Err(SortError::Unsupported { sorter: self.name() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sort_f32, sort_i32, sort_u32};
+    use crate::third;
+    use crate::SortOrder::{Ascending as Asc, Descending as Desc};
+    use crate::utils::new_u32_vec;
+
+    const LENGTHS: [usize; 12] = [0, 1, 7, 15, 16, 17, 32, 100, 1000, 1024, 4095, 65536];
+
+    fn i32_vec(n: usize) -> Vec<i32> {
+        new_u32_vec(n).into_iter().map(|v| v as i32).collect()
+    }
+
+    fn f32_vec(n: usize) -> Vec<f32> {
+        // 負の数、重複、±0.0 を含む値を作る
+        new_u32_vec(n).into_iter()
+            .map(|v| match v % 7 {
+                0 => 0.0,
+                1 => -0.0,
+                _ => (v as i32 >> 8) as f32 / 3.0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sort_u32_same_as_third() {
+        for &len in LENGTHS.iter() {
+            for order in &[Asc, Desc] {
+                let mut x = new_u32_vec(len);
+                let mut expected = x.clone();
+                assert_eq!(third::sort(&mut expected, order), Ok(()));
+
+                assert_eq!(sort_u32(&mut x, order), Ok(()));
+                assert_eq!(x, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn sort_i32_same_as_third() {
+        for &len in LENGTHS.iter() {
+            for order in &[Asc, Desc] {
+                let mut x = i32_vec(len);
+                let mut expected = x.clone();
+                assert_eq!(third::sort(&mut expected, order), Ok(()));
+
+                assert_eq!(sort_i32(&mut x, order), Ok(()));
+                assert_eq!(x, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn sort_f32_same_as_third() {
+        for &len in LENGTHS.iter() {
+            let mut x = f32_vec(len);
+            let mut expected = x.clone();
+            assert_eq!(third::sort_by(&mut expected, &|a, b| a.partial_cmp(b).unwrap()), Ok(()));
+
+            assert_eq!(sort_f32(&mut x, &Asc), Ok(()));
+            assert_eq!(x, expected);
+
+            // -0.0 と +0.0 は等しいので並び順は問わないが、どちらかが失われていないこと
+            let negative_zeros = |v: &[f32]| v.iter().filter(|f| f.to_bits() == (-0.0f32).to_bits()).count();
+            assert_eq!(negative_zeros(&x), negative_zeros(&expected));
+        }
+    }
+
+    #[test]
+    fn sort_f32_with_nan() {
+        let mut x = vec![3.0, f32::NAN, -1.0, 2.0, f32::NEG_INFINITY, 0.5, 8.0, 1.0,
+            4.0, -3.0, 5.0, 6.0, 7.0, 9.0, 10.0, 11.0, 12.0];
+
+        assert_eq!(sort_f32(&mut x, &Asc), Ok(()));
+
+        assert!(x[16].is_nan());
+        assert_eq!(&x[..5], &[f32::NEG_INFINITY, -3.0, -1.0, 0.5, 1.0]);
+    }
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    #[test]
+    fn sort_each_kernel() {
+        // 実行環境が対応している命令セットのカーネルはすべて試す。
+        // 2 の冪乗でない長さでは、n をまたぐレジスタの組をスカラーで処理する
+        let lengths = (0..=300).chain(vec![1000, 1024, 4095, 65536, 70000]);
+        for len in lengths {
+            let mut expected = new_u32_vec(len);
+            expected.sort();
+
+            if is_x86_feature_detected!("avx2") {
+                let mut x = new_u32_vec(len);
+                unsafe { super::x86::u32x::sort_avx2(&mut x) };
+                assert_eq!(x, expected, "avx2, len = {}", len);
+            }
+            if is_x86_feature_detected!("sse4.1") {
+                let mut x = new_u32_vec(len);
+                unsafe { super::x86::u32x::sort_sse41(&mut x) };
+                assert_eq!(x, expected, "sse4.1, len = {}", len);
+            }
+        }
+
+        for &len in &[17, 100, 1000] {
+            let mut expected = f32_vec(len);
+            expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let mut expected_i32 = i32_vec(len);
+            expected_i32.sort();
+
+            if is_x86_feature_detected!("avx2") {
+                let mut x = f32_vec(len);
+                unsafe { super::x86::f32x::sort_avx2(&mut x) };
+                assert_eq!(x, expected, "avx2, len = {}", len);
+                let mut x = i32_vec(len);
+                unsafe { super::x86::i32x::sort_avx2(&mut x) };
+                assert_eq!(x, expected_i32, "avx2, len = {}", len);
+            }
+            if is_x86_feature_detected!("sse4.1") {
+                let mut x = f32_vec(len);
+                unsafe { super::x86::f32x::sort_sse41(&mut x) };
+                assert_eq!(x, expected, "sse4.1, len = {}", len);
+                let mut x = i32_vec(len);
+                unsafe { super::x86::i32x::sort_sse41(&mut x) };
+                assert_eq!(x, expected_i32, "sse4.1, len = {}", len);
+            }
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::cmp::Ordering;
+
+// 複数のフィールドを順に比べる comparator を #[derive(SortKey)] で生成するためのトレイトと、
+// 生成したコードから呼ぶ比較関数。derive マクロ本体は rust-sort-derive クレートにある。
+//
+// 生成した比較は compare を通して、どのモジュールの sort_by にもそのまま渡せる。
+//
+//     third::sort_by(&mut students, &sort_key::compare)
+
+pub use rust_sort_derive::SortKey;
+
+/// フィールドの #[sort(...)] 属性に従って 2 つの値を比較する
+///
+/// 通常は #[derive(SortKey)] で実装する。使える属性は次のとおり
+///
+/// - `order = "asc" | "desc"`: そのフィールドの順序 (既定は "asc")
+/// - `priority = n`: 比較する順番。priority のあるフィールドを小さい順に比べ、残りのフィールドを宣言順に比べる
+/// - `nulls = "first" | "last"`: Option のフィールドで None を順序にかかわらず前か後ろに置く
+/// - `case_insensitive`: 文字列を大文字小文字を区別せずに比較する
+/// - `skip`: 比較に使わない
+pub trait SortKey {
+    fn sort_key_cmp(&self, other: &Self) -> Ordering;
+}
+
+/// 参照を並べたスライス (Vec<&Student> など) もそのままソートできるようにする
+impl<T: SortKey + ?Sized> SortKey for &T {
+    fn sort_key_cmp(&self, other: &Self) -> Ordering {
+        (**self).sort_key_cmp(*other)
+    }
+}
+
+/// sort_by に渡す comparator
+pub fn compare<T: SortKey>(a: &T, b: &T) -> Ordering {
+    a.sort_key_cmp(b)
+}
+
+/// 文字列を、文字ごとに小文字にしてから辞書順に比較する。小文字にした文字列は作らずに 1 文字ずつ比べる
+pub fn cmp_case_insensitive<S: AsRef<str> + ?Sized>(a: &S, b: &S) -> Ordering {
+    let a = a.as_ref().chars().flat_map(char::to_lowercase);
+    let b = b.as_ref().chars().flat_map(char::to_lowercase);
+    a.cmp(b)
+}
+
+/// Option を比較する。None 同士は等しく、None は nulls_last なら後ろ、そうでなければ前に置く。
+/// Some 同士は comparator で比較する
+pub fn cmp_option<T, F>(a: &Option<T>, b: &Option<T>, nulls_last: bool, comparator: F) -> Ordering
+    where F: FnOnce(&T, &T) -> Ordering
+{
+    let none = if nulls_last { Ordering::Greater } else { Ordering::Less };
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => none,
+        (Some(_), None) => none.reverse(),
+        (Some(a), Some(b)) => comparator(a, b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cmp_case_insensitive, compare, SortKey};
+    use crate::{fourth, iterative, odd_even, pairwise, select, third, SortError};
+    use crate::utils::new_u32_vec;
+    use std::cmp::Ordering;
+
+    #[derive(SortKey, PartialEq, Debug, Clone)]
+    struct Student {
+        #[sort(priority = 2, case_insensitive)]
+        first_name: String,
+        #[sort(priority = 1)]
+        last_name: String,
+        #[sort(skip)]
+        age: u8,
+    }
+
+    impl Student {
+        fn new(first_name: &str, last_name: &str, age: u8) -> Self {
+            Self {
+                first_name: first_name.to_string(),
+                last_name: last_name.to_string(),
+                age,
+            }
+        }
+    }
+
+    #[derive(SortKey, PartialEq, Debug, Clone)]
+    struct Record {
+        #[sort(order = "desc", nulls = "last")]
+        score: Option<u32>,
+        #[sort(case_insensitive, nulls = "first")]
+        name: Option<String>,
+        // Option でも属性がなければ Ord のとおり (降順なので None が後ろ)
+        #[sort(order = "desc")]
+        rank: Option<u8>,
+        id: usize,
+    }
+
+    #[derive(SortKey)]
+    struct Pair(#[sort(order = "desc")] u32, u32);
+
+    #[test]
+    fn sort_students_by_name() {
+        let taro = Student::new("Taro", "Yamada", 16);
+        let hanako = Student::new("hanako", "Yamada", 14);
+        let kyoko = Student::new("Kyoko", "Ito", 15);
+        let ryosuke = Student::new("Ryosuke", "Hayashi", 17);
+
+        // 参照のスライスでも、値のスライスでも同じ comparator を使える
+        let mut x = vec![&taro, &hanako, &kyoko, &ryosuke];
+        assert_eq!(third::sort_by(&mut x, &compare), Ok(()));
+        assert_eq!(x, vec![&ryosuke, &kyoko, &hanako, &taro]);
+
+        let mut x = vec![taro.clone(), hanako.clone(), kyoko.clone(), ryosuke.clone()];
+        assert_eq!(fourth::sort_by(&mut x, &compare), Ok(()));
+        assert_eq!(x, vec![ryosuke, kyoko, hanako, taro]);
+
+        // age は比較に使わない
+        assert_eq!(Student::new("A", "B", 1).sort_key_cmp(&Student::new("a", "B", 2)), Ordering::Equal);
+    }
+
+    #[test]
+    fn option_and_order_attributes() {
+        let record = |score, name: Option<&str>, rank, id| Record { score, name: name.map(String::from), rank, id };
+        let x = vec![
+            record(None, Some("b"), Some(1), 0),
+            record(Some(3), Some("B"), Some(1), 1),
+            record(Some(3), None, Some(1), 2),
+            record(Some(5), Some("a"), None, 3),
+            record(Some(5), Some("a"), Some(2), 4),
+            record(Some(3), Some("a"), Some(1), 5),
+            record(None, None, None, 6),
+        ];
+        let expected: Vec<usize> = vec![4, 3, 2, 5, 1, 6, 0];
+
+        // どのモジュールの sort_by でも同じ順になる
+        type Sort = fn(&mut [Record]) -> Result<(), SortError>;
+        let sorters: Vec<Sort> = vec![
+            |x| third::sort_by(x, &compare),
+            |x| fourth::sort_by(x, &compare),
+            |x| iterative::sort_by(x, &compare),
+            |x| odd_even::sort_by(x, &compare),
+            |x| pairwise::sort_by(x, &compare),
+        ];
+        for sort in sorters {
+            let mut y = x.clone();
+            assert_eq!(sort(&mut y), Ok(()));
+            assert_eq!(y.iter().map(|r| r.id).collect::<Vec<_>>(), expected);
+        }
+
+        let mut y = x.clone();
+        assert_eq!(select::select_nth(&mut y, 0, &compare).map(|r| r.id), Ok(4));
+    }
+
+    #[test]
+    fn tuple_struct() {
+        let mut x: Vec<Pair> = new_u32_vec(100).into_iter().map(|v| Pair(v % 4, v)).collect();
+        assert_eq!(third::sort_by(&mut x, &compare), Ok(()));
+        assert!(x.windows(2).all(|w| w[0].0 > w[1].0 || (w[0].0 == w[1].0 && w[0].1 <= w[1].1)));
+    }
+
+    #[test]
+    fn case_insensitive() {
+        assert_eq!(cmp_case_insensitive("Rust", "rust"), Ordering::Equal);
+        assert_eq!(cmp_case_insensitive("GC", "and"), Ordering::Greater);
+        assert_eq!(cmp_case_insensitive("ÉCOLE", "école"), Ordering::Equal);
+        // 一方が他方の先頭部分なら短いほうが前
+        assert_eq!(cmp_case_insensitive("Sort", "sorter"), Ordering::Less);
+        // 'İ' は小文字にすると 2 文字 ("i\u{307}") になる
+        assert_eq!(cmp_case_insensitive("İ", "i"), Ordering::Greater);
+    }
+}
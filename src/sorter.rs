@@ -0,0 +1,169 @@
+use super::{SortError, SortOrder};
+use std::cmp::Ordering;
+
+/// ソート実装ごとの性質
+/// 
+/// ツールやテストから全実装を同じように扱うときに、どの入力を渡せるかを判断するために使う
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// rayon で並列にソートする
+    pub parallel: bool,
+    /// u32 以外の型 (ジェネリクス) をソートできる
+    pub generic: bool,
+    /// 長さが 2 の冪乗のスライスしかソートできない
+    pub power_of_two_only: bool,
+    /// 任意の比較関数 (comparator) でソートできる
+    pub comparator: bool,
+}
+
+/// first, second, third, fourth の各モジュールを共通のインターフェースで呼び出すためのトレイト
+/// 
+/// モジュールごとに引数や要素型の制約が違う (first は u32 と bool、second 以降は T と SortOrder) ので、
+/// 各モジュールに用意したサイズ 0 の型 (`first::First` など) でこのトレイトを実装して差分を吸収する。
+/// `Box<dyn Sorter<T>>` として扱えるように、要素型 T はメソッドではなくトレイトのパラメータにしている
+pub trait Sorter<T> {
+    /// レジストリで引くときの名前
+    fn name(&self) -> &'static str;
+
+    fn capabilities(&self) -> Capabilities;
+
+    fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError>;
+
+    /// comparator に対応していない実装は `SortError::Unsupported` を返す
+    fn sort_by(&self, x: &mut [T], comparator: &(dyn Fn(&T, &T) -> Ordering + Sync)) -> Result<(), SortError>;
+}
+
+/// 名前から `Sorter` を引くためのレジストリ
+pub struct Registry<T> {
+    sorters: Vec<Box<dyn Sorter<T>>>,
+}
+
+impl<T> Registry<T> {
+    /// 空のレジストリを作る
+    pub fn new() -> Self {
+        Self { sorters: Vec::new() }
+    }
+
+    /// sorter を登録する。同じ名前の sorter が既にあれば置き換える
+    pub fn register(&mut self, sorter: Box<dyn Sorter<T>>) {
+        self.sorters.retain(|s| s.name() != sorter.name());
+        self.sorters.push(sorter);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Sorter<T>> {
+        self.iter().find(|s| s.name() == name)
+    }
+
+    /// 登録順に sorter を返す
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Sorter<T>> {
+        self.sorters.iter().map(|s| s.as_ref())
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.iter().map(|s| s.name()).collect()
+    }
+}
+
+impl<T> Default for Registry<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord + Send> Registry<T> {
+    /// ジェネリクスに対応した sorter をすべて登録したレジストリ
+    /// 
+    /// 新しいソートのモジュールを追加したら、その `Sorter` 実装もここに登録する
+    pub fn generic() -> Self {
+        let mut registry = Self::new();
+        registry.register(Box::new(crate::second::Second));
+        registry.register(Box::new(crate::third::Third));
+        registry.register(Box::new(crate::fourth::Fourth));
+        registry.register(Box::new(crate::iterative::Iterative));
+        registry.register(Box::new(crate::iterative::ParIterative));
+        registry.register(Box::new(crate::odd_even::OddEven));
+        registry.register(Box::new(crate::odd_even::ParOddEven));
+        registry.register(Box::new(crate::pairwise::Pairwise));
+        registry.register(Box::new(crate::pairwise::ParPairwise));
+        registry
+    }
+}
+
+impl Registry<u32> {
+    /// u32 専用の first (と feature "simd" が有効なら simd) も含めて、すべての sorter を登録したレジストリ
+    pub fn with_all() -> Self {
+        let mut registry = Self::new();
+        registry.register(Box::new(crate::first::First));
+        for sorter in Self::generic().sorters {
+            registry.register(sorter);
+        }
+        #[cfg(feature = "simd")]
+        registry.register(Box::new(crate::simd::Simd));
+        registry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Registry;
+    use crate::{SortError, SortOrder::{Ascending as Asc, Descending as Desc}};
+    use crate::utils::{new_u32_vec, is_sorted};
+
+    #[test]
+    fn registry_names() {
+        let generic = vec![
+            "second", "third", "fourth", "iterative", "iterative_par", "odd_even", "odd_even_par", "pairwise", "pairwise_par",
+        ];
+        assert_eq!(Registry::<&str>::generic().names(), generic);
+
+        let mut all = vec!["first"];
+        all.extend(generic);
+        if cfg!(feature = "simd") {
+            all.push("simd");
+        }
+        assert_eq!(Registry::<u32>::with_all().names(), all);
+        assert!(Registry::<u32>::with_all().get("fifth").is_none());
+    }
+
+    #[test]
+    fn sort_u32_with_every_sorter() {
+        for sorter in Registry::<u32>::with_all().iter() {
+            let mut x = new_u32_vec(1024);
+            assert_eq!(sorter.sort(&mut x, &Asc), Ok(()), "{}", sorter.name());
+            assert!(is_sorted(&x, &Asc), "{}", sorter.name());
+
+            assert_eq!(sorter.sort(&mut x, &Desc), Ok(()), "{}", sorter.name());
+            assert!(is_sorted(&x, &Desc), "{}", sorter.name());
+        }
+    }
+
+    #[test]
+    fn sort_by_with_every_sorter() {
+        for sorter in Registry::<u32>::with_all().iter() {
+            let mut x = new_u32_vec(1000);
+            let result = sorter.sort_by(&mut x, &|a, b| b.cmp(a));
+
+            if sorter.capabilities().comparator {
+                assert_eq!(result, Ok(()), "{}", sorter.name());
+                assert!(is_sorted(&x, &Desc), "{}", sorter.name());
+            } else {
+                assert_eq!(result, Err(SortError::Unsupported { sorter: sorter.name() }));
+            }
+        }
+    }
+
+    #[test]
+    fn power_of_two_only_sorters_reject_other_lengths() {
+        for sorter in Registry::<u32>::with_all().iter() {
+            let mut x = new_u32_vec(1000);
+            let result = sorter.sort(&mut x, &Asc);
+
+            if sorter.capabilities().power_of_two_only {
+                assert_eq!(result, Err(SortError::InvalidLength { len: 1000 }), "{}", sorter.name());
+            } else {
+                assert_eq!(result, Ok(()), "{}", sorter.name());
+                assert!(is_sorted(&x, &Asc), "{}", sorter.name());
+            }
+        }
+    }
+}
@@ -0,0 +1,373 @@
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+
+// bitonic network がデータに対して行う操作は「i 番目と j 番目を比較する」「i 番目と j 番目を入れ替える」の 2 つだけ。
+// そこでこの 2 つをトレイトにして、スライス以外 (VecDeque、複数の列、一定間隔で飛び飛びに並んだ要素) も
+// 同じ network (third::sort_storage) で並べ替えられるようにする。
+
+/// bitonic network で並べ替えられる入れ物
+pub trait SortStorage {
+    /// 要素数
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// i 番目と j 番目の要素を比較する
+    fn compare(&self, i: usize, j: usize) -> Ordering;
+
+    /// i 番目と j 番目の要素を入れ替える
+    fn swap(&mut self, i: usize, j: usize);
+}
+
+impl<T: Ord> SortStorage for [T] {
+    fn len(&self) -> usize {
+        <[T]>::len(self)
+    }
+
+    fn compare(&self, i: usize, j: usize) -> Ordering {
+        self[i].cmp(&self[j])
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        <[T]>::swap(self, i, j)
+    }
+}
+
+impl<T: Ord> SortStorage for Vec<T> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn compare(&self, i: usize, j: usize) -> Ordering {
+        self[i].cmp(&self[j])
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        <[T]>::swap(self, i, j)
+    }
+}
+
+/// リングバッファも make_contiguous せずにそのまま並べ替えられる
+impl<T: Ord> SortStorage for VecDeque<T> {
+    fn len(&self) -> usize {
+        VecDeque::len(self)
+    }
+
+    fn compare(&self, i: usize, j: usize) -> Ordering {
+        self[i].cmp(&self[j])
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        VecDeque::swap(self, i, j)
+    }
+}
+
+/// スライスを comparator の順序で比較する SortStorage
+pub struct ByComparator<'a, T, F> {
+    data: &'a mut [T],
+    comparator: &'a F,
+}
+
+impl<'a, T, F> ByComparator<'a, T, F>
+    where F: Fn(&T, &T) -> Ordering
+{
+    pub fn new(data: &'a mut [T], comparator: &'a F) -> Self {
+        Self { data, comparator }
+    }
+}
+
+impl<T, F> SortStorage for ByComparator<'_, T, F>
+    where F: Fn(&T, &T) -> Ordering
+{
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn compare(&self, i: usize, j: usize) -> Ordering {
+        (self.comparator)(&self.data[i], &self.data[j])
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        self.data.swap(i, j)
+    }
+}
+
+/// 前後 2 つに分けて、それぞれを別のスレッドで並べ替えられる SortStorage
+///
+/// fourth の並列 network はこのトレイトだけを使う。分けた 2 つは重ならないので同時に可変参照として持てて、
+/// 2 つの間での比較と入れ替えは compare_with, swap_with で行う
+pub trait SplitStorage: SortStorage {
+    /// split_at_mut で分けた片側の型
+    type Part<'b>: SplitStorage + Send where Self: 'b;
+
+    /// mid 番目の前と後ろに分ける
+    fn split_at_mut(&mut self, mid: usize) -> (Self::Part<'_>, Self::Part<'_>);
+
+    /// self の i 番目と other の j 番目を比較する
+    fn compare_with(&self, i: usize, other: &Self, j: usize) -> Ordering;
+
+    /// self の i 番目と other の j 番目を入れ替える
+    fn swap_with(&mut self, i: usize, other: &mut Self, j: usize);
+}
+
+impl<'a, T, F> SplitStorage for ByComparator<'a, T, F>
+    where T: Send,
+        F: Sync + Fn(&T, &T) -> Ordering,
+{
+    type Part<'b> = ByComparator<'b, T, F> where Self: 'b;
+
+    fn split_at_mut(&mut self, mid: usize) -> (Self::Part<'_>, Self::Part<'_>) {
+        let (first, second) = self.data.split_at_mut(mid);
+        (ByComparator::new(first, self.comparator), ByComparator::new(second, self.comparator))
+    }
+
+    fn compare_with(&self, i: usize, other: &Self, j: usize) -> Ordering {
+        (self.comparator)(&self.data[i], &other.data[j])
+    }
+
+    fn swap_with(&mut self, i: usize, other: &mut Self, j: usize) {
+        std::mem::swap(&mut self.data[i], &mut other.data[j])
+    }
+}
+
+/// キーの SortStorage と、キーと一緒に並べ替える値のスライスの組
+///
+/// Columns と違って値の列は 1 つだけだが、前後に分割できるので fourth の並列 network でも使える
+pub struct WithValues<'a, S, V> {
+    keys: S,
+    values: &'a mut [V],
+}
+
+impl<'a, S: SortStorage, V> WithValues<'a, S, V> {
+    /// 長さがキーと違う場合は panic する
+    pub fn new(keys: S, values: &'a mut [V]) -> Self {
+        assert_eq!(keys.len(), values.len(), "values must have the same length as the keys");
+        Self { keys, values }
+    }
+}
+
+impl<S: SortStorage, V> SortStorage for WithValues<'_, S, V> {
+    fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    fn compare(&self, i: usize, j: usize) -> Ordering {
+        self.keys.compare(i, j)
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        self.keys.swap(i, j);
+        self.values.swap(i, j);
+    }
+}
+
+impl<S: SplitStorage, V: Send> SplitStorage for WithValues<'_, S, V> {
+    type Part<'b> = WithValues<'b, S::Part<'b>, V> where Self: 'b;
+
+    fn split_at_mut(&mut self, mid: usize) -> (Self::Part<'_>, Self::Part<'_>) {
+        let (keys_first, keys_second) = self.keys.split_at_mut(mid);
+        let (values_first, values_second) = self.values.split_at_mut(mid);
+        (
+            WithValues { keys: keys_first, values: values_first },
+            WithValues { keys: keys_second, values: values_second },
+        )
+    }
+
+    fn compare_with(&self, i: usize, other: &Self, j: usize) -> Ordering {
+        self.keys.compare_with(i, &other.keys, j)
+    }
+
+    fn swap_with(&mut self, i: usize, other: &mut Self, j: usize) {
+        self.keys.swap_with(i, &mut other.keys, j);
+        std::mem::swap(&mut self.values[i], &mut other.values[j]);
+    }
+}
+
+/// 比較には使わないが、キーと一緒に並べ替える列
+pub trait Column {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn swap(&mut self, i: usize, j: usize);
+}
+
+impl<T> Column for [T] {
+    fn len(&self) -> usize {
+        <[T]>::len(self)
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        <[T]>::swap(self, i, j)
+    }
+}
+
+impl<T> Column for Vec<T> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        <[T]>::swap(self, i, j)
+    }
+}
+
+impl<T> Column for VecDeque<T> {
+    fn len(&self) -> usize {
+        VecDeque::len(self)
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        VecDeque::swap(self, i, j)
+    }
+}
+
+/// `&mut [T]` のような借用もそのまま列として渡せる
+impl<C: Column + ?Sized> Column for &mut C {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        (**self).swap(i, j)
+    }
+}
+
+/// 列ごとに別の配列になっているデータ (struct of arrays) をまとめた SortStorage
+///
+/// 比較はキーの列だけで行い、入れ替えはすべての列に対して行う
+pub struct Columns<'a, K: ?Sized> {
+    keys: &'a mut K,
+    columns: Vec<&'a mut dyn Column>,
+}
+
+impl<'a, K: SortStorage + ?Sized> Columns<'a, K> {
+    pub fn new(keys: &'a mut K) -> Self {
+        Self { keys, columns: Vec::new() }
+    }
+
+    /// キーと一緒に並べ替える列を追加する。長さがキーと違う場合は panic する
+    pub fn with(mut self, column: &'a mut dyn Column) -> Self {
+        assert_eq!(self.keys.len(), column.len(), "every column must have the same length as the keys");
+        self.columns.push(column);
+        self
+    }
+}
+
+impl<K: SortStorage + ?Sized> SortStorage for Columns<'_, K> {
+    fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    fn compare(&self, i: usize, j: usize) -> Ordering {
+        self.keys.compare(i, j)
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        self.keys.swap(i, j);
+        for column in self.columns.iter_mut() {
+            column.swap(i, j);
+        }
+    }
+}
+
+/// スライスの start 番目から stride おきに並んだ要素だけを見る SortStorage
+///
+/// 行優先で並んだ行列の 1 列だけをソートする場合などに使う。見えていない要素は動かない
+pub struct Strided<'a, T> {
+    data: &'a mut [T],
+    start: usize,
+    stride: usize,
+}
+
+impl<'a, T: Ord> Strided<'a, T> {
+    /// stride が 0 の場合は panic する
+    pub fn new(data: &'a mut [T], start: usize, stride: usize) -> Self {
+        assert!(stride > 0, "stride must be positive");
+        Self { data, start, stride }
+    }
+
+    fn index(&self, i: usize) -> usize {
+        self.start + i * self.stride
+    }
+}
+
+impl<T: Ord> SortStorage for Strided<'_, T> {
+    fn len(&self) -> usize {
+        if self.start >= self.data.len() {
+            0
+        } else {
+            (self.data.len() - self.start - 1) / self.stride + 1
+        }
+    }
+
+    fn compare(&self, i: usize, j: usize) -> Ordering {
+        self.data[self.index(i)].cmp(&self.data[self.index(j)])
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        let (i, j) = (self.index(i), self.index(j));
+        self.data.swap(i, j)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ByComparator, Columns, SortStorage, SplitStorage, Strided, WithValues};
+    use std::cmp::Ordering;
+
+    #[test]
+    fn strided_len() {
+        let mut x = [0u32; 10];
+        assert_eq!(Strided::new(&mut x, 0, 3).len(), 4);
+        assert_eq!(Strided::new(&mut x, 2, 3).len(), 3);
+        assert_eq!(Strided::new(&mut x, 9, 3).len(), 1);
+        assert_eq!(Strided::new(&mut x, 10, 3).len(), 0);
+    }
+
+    #[test]
+    fn columns_swap_every_column() {
+        let mut keys = vec![2u32, 1];
+        let mut names = vec!["b", "a"];
+        let mut scores = vec![20.0, 10.0];
+
+        let mut columns = Columns::new(&mut keys).with(&mut names).with(&mut scores);
+        assert_eq!(columns.compare(0, 1), Ordering::Greater);
+        columns.swap(0, 1);
+
+        assert_eq!(keys, vec![1, 2]);
+        assert_eq!(names, vec!["a", "b"]);
+        assert_eq!(scores, vec![10.0, 20.0]);
+    }
+
+    #[test]
+    fn with_values_split() {
+        let mut keys = vec![4u32, 3, 2, 1];
+        let mut values = vec!["d", "c", "b", "a"];
+        let comparator = |a: &u32, b: &u32| a.cmp(b);
+
+        let mut storage = WithValues::new(ByComparator::new(&mut keys, &comparator), &mut values);
+        {
+            let (mut first, mut second) = storage.split_at_mut(2);
+            assert_eq!((first.len(), second.len()), (2, 2));
+            assert_eq!(first.compare_with(0, &second, 1), Ordering::Greater);
+            first.swap_with(0, &mut second, 1);
+        }
+        storage.swap(1, 2);
+
+        assert_eq!(keys, vec![1, 2, 3, 4]);
+        assert_eq!(values, vec!["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "same length")]
+    fn columns_length_mismatch() {
+        let mut keys = vec![2u32, 1];
+        let mut names = vec!["b"];
+        let _ = Columns::new(&mut keys).with(&mut names);
+    }
+}
@@ -1,8 +1,14 @@
-use super::SortOrder;
-use std::{cmp::Ordering};
+use super::{NanPolicy, SortError, SortOrder};
+use crate::float::{self, Float};
+use crate::leaf::{self, LeafConfig, LeafSort};
+use crate::network::Network;
+use crate::sorter::{Capabilities, Sorter};
+use crate::storage::{ByComparator, Columns, SortStorage};
+use crate::utils::{apply_permutation, bitonic_turn, catch_comparator_panic, greatest_power_of_two_below};
+use std::{cell::{Cell, RefCell}, cmp::Ordering};
 
 
-pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), String> {
+pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
     match *order {
         SortOrder::Ascending  => sort_by(x, &|a, b| a.cmp(b)),
         SortOrder::Descending => sort_by(x, &|a, b| b.cmp(a)),
@@ -17,46 +23,436 @@ pub fn sort<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<(), String> {
 //
 // なので、comparator のところに具体的な型は書けない。
 // -> クロージャを引数にとる場合はジェネリクスにする必要がある
-pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), String>
+pub fn sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
     where F: Fn(&T, &T) -> Ordering
 {
-    if x.len().is_power_of_two() {
-        do_sort(x, true, comparator);
-        Ok(())
+    // ソート済み・逆順・bitonic 列の入力は、前半と後半をソートする段 (do_sort の再帰) を飛ばして、
+    // マージ段だけで済ませる。bitonic 列でなければ先頭の数要素を見た時点で判定が終わる
+    if catch_comparator_panic(|| sort_bitonic(x, comparator))? {
+        return Ok(());
+    }
+    sort_by_with_leaf(x, comparator, &LeafConfig::default())
+}
+
+/// x が comparator で見て bitonic 列 (単調な列を含む) なら、マージ段だけでソートして true を返す
+fn sort_bitonic<T, F>(x: &mut [T], comparator: &F) -> bool
+    where F: Fn(&T, &T) -> Ordering
+{
+    let (turn, ascending) = match bitonic_turn(x, comparator) {
+        Some(turn) => turn,
+        None => return false,
+    };
+    let n = x.len();
+    if turn == n {
+        // 単調な列は、逆順なら反転するだけ
+        if !ascending {
+            x.reverse();
+        }
+    } else if ascending {
+        // 上って下る列は、逆向きのマージ段で降順にしてから反転する
+        sub_sort(&mut ByComparator::new(x, comparator), 0, n, false);
+        x.reverse();
     } else {
-        Err(format!("The length of x is not a power of two. x.len(): {}", x.len()))
+        // 下って上る列は、そのままマージ段に渡せる
+        sub_sort(&mut ByComparator::new(x, comparator), 0, n, true);
+    }
+    true
+}
+
+/// 再帰を打ち切る leaf の大きさとソート方法を指定してソートする
+pub fn sort_by_with_leaf<T, F>(x: &mut [T], comparator: &F, leaf: &LeafConfig) -> Result<(), SortError>
+    where F: Fn(&T, &T) -> Ordering
+{
+    // 2 の冪乗でない長さにも対応した bitonic sort (任意長の bitonic network) なので、
+    // 長さのチェックやパディングは不要。0 要素、1 要素のスライスはそのまま返る。
+    // comparator が panic した場合は SortError::ComparatorPanic を返す
+    let n = x.len();
+    catch_comparator_panic(|| do_sort(&mut ByComparator::new(x, comparator), 0, n, true, leaf))
+}
+
+/// `SortStorage` を実装した入れ物 (VecDeque、`storage::Columns`、`storage::Strided` など) をソートする
+pub fn sort_storage<S>(s: &mut S, order: &SortOrder) -> Result<(), SortError>
+    where S: SortStorage + ?Sized
+{
+    let forward = match *order {
+        SortOrder::Ascending  => true,
+        SortOrder::Descending => false,
+    };
+    let n = s.len();
+    do_sort(s, 0, n, forward, &LeafConfig::default());
+    Ok(())
+}
+
+/// leaf の設定で third の sort_by_with_leaf が実行するソーティングネットワークを取り出す
+/// 
+/// どの組を比較するかが比較の結果によらないことを利用して、実際の do_sort を記録用の入れ物 (Recorder) に対して実行する。
+/// 挿入ソートの leaf は比較の結果によって次に比較する組が変わるのでネットワークにならず、`SortError::Unsupported` を返す
+pub fn network(n: usize, leaf: &LeafConfig) -> Result<Network, SortError> {
+    if leaf.sort == LeafSort::Insertion && leaf.cutoff() > 1 {
+        return Err(SortError::Unsupported { sorter: "third" });
+    }
+    let mut recorder = Recorder { n, comparators: RefCell::new(Vec::new()) };
+    do_sort(&mut recorder, 0, n, true, leaf);
+    Ok(Network::from_comparators(n, recorder.comparators.into_inner()))
+}
+
+/// PartialOrd だけを実装した型をソートする
+/// 
+/// `partial_cmp` が `None` を返す (比較できない) 組が見つかった場合は、x を変更せずに
+/// その組のインデックス (元の x での位置) を `SortError::Incomparable` で返す。
+/// 返すのは、ソートの途中で実際に比較した組のうち (left, right) が辞書順で最小のもの (fourth::sort_partial と同じ)。
+/// どの組を比較するかは要素の並び (データ) によって変わるので、x 全体で辞書順最小の比較できない組とは限らない。
+/// 比較できない組があるまま network を実行すると、ソートされていない結果が黙って返ってしまうため
+pub fn sort_partial<T: PartialOrd>(x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+    let ascending = matches!(*order, SortOrder::Ascending);
+    let incomparable: Cell<Option<(usize, usize)>> = Cell::new(None);
+
+    // 要素を動かす前に比較できない組を見つけたいので、インデックスの列をソートしてから並べ替える
+    let mut indices: Vec<usize> = (0..x.len()).collect();
+    {
+        let x: &[T] = x;
+        sort_by_with_leaf(&mut indices, &|&a: &usize, &b: &usize| {
+            let ordering = match x[a].partial_cmp(&x[b]) {
+                Some(ordering) => if ascending { ordering } else { ordering.reverse() },
+                None => {
+                    let pair = (a.min(b), a.max(b));
+                    match incomparable.get() {
+                        Some(f) if f <= pair => {},
+                        _ => incomparable.set(Some(pair)),
+                    }
+                    Ordering::Equal
+                },
+            };
+            ordering.then(a.cmp(&b))
+        }, &LeafConfig::default())?;
+    }
+
+    if let Some((left, right)) = incomparable.get() {
+        return Err(SortError::Incomparable { left, right });
+    }
+    apply_permutation(x, &indices);
+    Ok(())
+}
+
+/// f32, f64 のスライスを NaN の扱い (policy) を指定してソートする
+/// 
+/// policy が `NanPolicy::Error` で NaN が含まれている場合は、x を変更せずに最初の NaN の位置を返す
+pub fn sort_floats<T: Float>(x: &mut [T], order: &SortOrder, policy: NanPolicy) -> Result<(), SortError> {
+    sort_floats_by(x, &|v: &T| *v, order, policy)
+}
+
+/// key で取り出した浮動小数点数の順にソートする。NaN の扱いは sort_floats と同じ
+pub fn sort_floats_by<T, K, F>(x: &mut [T], key: &F, order: &SortOrder, policy: NanPolicy) -> Result<(), SortError>
+    where K: Float,
+        F: Fn(&T) -> K,
+{
+    float::check_nan(x, key, policy)?;
+    let comparator = float::comparator(order, policy);
+    sort_by(x, &|a, b| comparator(&key(a), &key(b)))
+}
+
+/// key で取り出した値の昇順にソートする。key は比較のたびに呼ばれる
+pub fn sort_by_key<T, K, F>(x: &mut [T], key: &F) -> Result<(), SortError>
+    where K: Ord,
+        F: Fn(&T) -> K,
+{
+    sort_by(x, &|a, b| key(a).cmp(&key(b)))
+}
+
+/// key で取り出した値の昇順にソートする。key は各要素について 1 回だけ呼ばれる
+/// 
+/// key の計算が重い場合 (文字列のパースや小文字化など) 向け。(key, 元のインデックス) の組を先に計算して
+/// bitonic network でソートし、その順序で x を並べ替える。組にインデックスが入っているので、結果は安定ソートになる
+pub fn sort_by_cached_key<T, K, F>(x: &mut [T], key: &F) -> Result<(), SortError>
+    where K: Ord,
+        F: Fn(&T) -> K,
+{
+    let mut keys: Vec<(K, usize)> = x.iter()
+        .map(key)
+        .enumerate()
+        .map(|(i, k)| (k, i))
+        .collect();
+    sort_by(&mut keys, &|a, b| a.cmp(b))?;
+
+    let perm: Vec<usize> = keys.into_iter().map(|(_, i)| i).collect();
+    apply_permutation(x, &perm);
+    Ok(())
+}
+
+/// keys と values を組として、keys の順序でソートする
+/// 
+/// 別々のスライス (カラム) に分かれているデータ向け。network は keys に対して実行し、
+/// keys で行った swap をすべて values にも同じ位置で行う。長さが違う場合は `SortError::LengthMismatch` を返す
+pub fn sort_pairs<K: Ord, V>(keys: &mut [K], values: &mut [V], order: &SortOrder) -> Result<(), SortError> {
+    match *order {
+        SortOrder::Ascending  => sort_pairs_by(keys, values, &|a, b| a.cmp(b)),
+        SortOrder::Descending => sort_pairs_by(keys, values, &|a, b| b.cmp(a)),
+    }
+}
+
+pub fn sort_pairs_by<K, V, F>(keys: &mut [K], values: &mut [V], comparator: &F) -> Result<(), SortError>
+    where F: Fn(&K, &K) -> Ordering
+{
+    if keys.len() != values.len() {
+        return Err(SortError::LengthMismatch { keys: keys.len(), values: values.len() });
+    }
+    let n = keys.len();
+    let mut keys = ByComparator::new(keys, comparator);
+    let mut values = values;
+    do_sort(&mut Columns::new(&mut keys).with(&mut values), 0, n, true, &LeafConfig::default());
+    Ok(())
+}
+
+/// x をソートしたときの並び順を、インデックスの列で返す。x 自体は変更しない
+/// 
+/// 戻り値を perm とすると、x[perm[0]], x[perm[1]], ... がソート済みの順序になる。
+/// 複数の列 (カラム) を同じ順序で並べ替えたいときは、`utils::apply_permutation` でそれぞれに適用する
+pub fn argsort<T>(x: &[T], order: &SortOrder) -> Result<Vec<usize>, SortError>
+    where T: Ord,
+{
+    match *order {
+        SortOrder::Ascending  => argsort_by(x, &|a, b| a.cmp(b)),
+        SortOrder::Descending => argsort_by(x, &|a, b| b.cmp(a)),
+    }
+}
+
+/// comparator の順序での argsort。comparator で等しい要素は元のインデックス順に並ぶ (安定)
+pub fn argsort_by<T, F>(x: &[T], comparator: &F) -> Result<Vec<usize>, SortError>
+    where F: Fn(&T, &T) -> Ordering
+{
+    // 等しい要素は元のインデックスで比較するので、インデックスの列に対しては全順序になる
+    let mut indices: Vec<usize> = (0..x.len()).collect();
+    sort_by(&mut indices, &|&a: &usize, &b: &usize| comparator(&x[a], &x[b]).then(a.cmp(&b)))?;
+    Ok(indices)
+}
+
+/// 安定ソート。comparator で等しいと判定された要素は、元の順序を保つ
+/// 
+/// bitonic sort は離れた要素同士を交換するので安定ではない。そこで要素そのものではなく
+/// インデックスの列を argsort_by でソートし、最後にその置換で x を並べ替える。要素をコピーしないので T: Clone は不要
+pub fn stable_sort_by<T, F>(x: &mut [T], comparator: &F) -> Result<(), SortError>
+    where F: Fn(&T, &T) -> Ordering
+{
+    let indices = argsort_by(x, comparator)?;
+    apply_permutation(x, &indices);
+    Ok(())
+}
+
+/// 前半 x[..mid] と後半 x[mid..] がそれぞれ order の順にソート済みのとき、x 全体をその順にマージする
+/// 
+/// 前半を反転すると x 全体が「下って上る」bitonic 列になるので、do_sort の最後と同じマージ段 (sub_sort) だけでソートできる。
+/// 前半と後半の長さは違っていてよい。mid > x.len() の場合は `SortError::InvalidLength` を返す
+pub fn bitonic_merge<T: Ord>(x: &mut [T], mid: usize, order: &SortOrder) -> Result<(), SortError> {
+    match *order {
+        SortOrder::Ascending  => bitonic_merge_by(x, mid, &|a, b| a.cmp(b)),
+        SortOrder::Descending => bitonic_merge_by(x, mid, &|a, b| b.cmp(a)),
+    }
+}
+
+pub fn bitonic_merge_by<T, F>(x: &mut [T], mid: usize, comparator: &F) -> Result<(), SortError>
+    where F: Fn(&T, &T) -> Ordering
+{
+    if mid > x.len() {
+        return Err(SortError::InvalidLength { len: x.len() });
+    }
+    x[..mid].reverse();
+    let n = x.len();
+    sub_sort(&mut ByComparator::new(x, comparator), 0, n, true);
+    Ok(())
+}
+
+/// x を先頭から runs の長さずつ区切った区間 (ラン) がそれぞれ order の順にソート済みのとき、x 全体をマージする
+/// 
+/// 隣り合うランを bitonic_merge で 2 つずつマージすることを、ランが 1 つになるまで繰り返す。
+/// runs の合計が x.len() と違う場合は `SortError::InvalidLength` を返す
+pub fn merge_runs<T: Ord>(x: &mut [T], runs: &[usize], order: &SortOrder) -> Result<(), SortError> {
+    match *order {
+        SortOrder::Ascending  => merge_runs_by(x, runs, &|a, b| a.cmp(b)),
+        SortOrder::Descending => merge_runs_by(x, runs, &|a, b| b.cmp(a)),
+    }
+}
+
+pub fn merge_runs_by<T, F>(x: &mut [T], runs: &[usize], comparator: &F) -> Result<(), SortError>
+    where F: Fn(&T, &T) -> Ordering
+{
+    if runs.iter().sum::<usize>() != x.len() {
+        return Err(SortError::InvalidLength { len: x.len() });
+    }
+
+    let mut runs = runs.to_vec();
+    while runs.len() > 1 {
+        let mut merged = Vec::with_capacity(runs.len().div_ceil(2));
+        let mut start = 0;
+        for pair in runs.chunks(2) {
+            let len = pair.iter().sum::<usize>();
+            if let [first, _] = *pair {
+                bitonic_merge_by(&mut x[start..start + len], first, comparator)?;
+            }
+            merged.push(len);
+            start += len;
+        }
+        runs = merged;
+    }
+    Ok(())
+}
+
+/// bitonic 列 x を half-cleaner で 2 つの bitonic 列に分け、分割位置 mid を返す
+/// 
+/// x[i] と x[i + mid] を比較して順序が逆なら入れ替えるので、x[..mid] のどの要素も x[mid..] のどの要素の前に来る順序になる。
+/// mid は x.len() 未満で最大の 2 の冪乗。x.len() が 2 の冪乗でない場合は、x は order で見て「下って上る」列である必要がある
+/// (2 の冪乗なら上って下る列でもよい)。x.len() < 2 の場合は分割できないので `SortError::InvalidLength` を返す
+pub fn bitonic_split<T: Ord>(x: &mut [T], order: &SortOrder) -> Result<usize, SortError> {
+    match *order {
+        SortOrder::Ascending  => bitonic_split_by(x, &|a, b| a.cmp(b)),
+        SortOrder::Descending => bitonic_split_by(x, &|a, b| b.cmp(a)),
+    }
+}
+
+pub fn bitonic_split_by<T, F>(x: &mut [T], comparator: &F) -> Result<usize, SortError>
+    where F: Fn(&T, &T) -> Ordering
+{
+    let n = x.len();
+    if n < 2 {
+        return Err(SortError::InvalidLength { len: n });
+    }
+    let mid_point = greatest_power_of_two_below(n);
+    compare_and_swap(&mut ByComparator::new(x, comparator), 0, n, mid_point, true);
+    Ok(mid_point)
+}
+
+/// x のうち order の順で先頭に来る k 要素だけを、ソート済みの状態で x[..k] に集める。x[k..] の順序は不定
+/// 
+/// x[..k] を k 要素のバッファとして、残りを k 要素ずつのブロックに区切ってソートし、バッファとマージしては
+/// 大きい方を捨てる (ブロック側に戻す)。k が x.len() 以上の場合は x 全体をソートする
+pub fn partial_sort<T: Ord>(x: &mut [T], k: usize, order: &SortOrder) -> Result<(), SortError> {
+    match *order {
+        SortOrder::Ascending  => partial_sort_by(x, k, &|a, b| a.cmp(b)),
+        SortOrder::Descending => partial_sort_by(x, k, &|a, b| b.cmp(a)),
+    }
+}
+
+pub fn partial_sort_by<T, F>(x: &mut [T], k: usize, comparator: &F) -> Result<(), SortError>
+    where F: Fn(&T, &T) -> Ordering
+{
+    let k = k.min(x.len());
+    if k > 0 {
+        top_k_in_place(x, k, comparator);
+        x[..k].reverse();
+    }
+    Ok(())
+}
+
+/// iter の要素のうち comparator の順で先頭に来る k 要素を、ソート済みの Vec で返す
+/// 
+/// 要素をすべて集めてからソートするのではなく、k 要素のバッファに k 要素ずつのブロックをマージしていくので、
+/// 使うメモリは要素数によらず 2k 程度で済む
+pub fn top_k<I, F>(iter: I, k: usize, comparator: &F) -> Result<Vec<I::Item>, SortError>
+    where I: IntoIterator,
+        F: Fn(&I::Item, &I::Item) -> Ordering,
+{
+    if k == 0 {
+        return Ok(Vec::new());
+    }
+    let mut iter = iter.into_iter();
+
+    // バッファは comparator の逆順 (捨てる候補が先頭) に保つ
+    let mut buffer: Vec<I::Item> = iter.by_ref().take(k).collect();
+    let n = buffer.len();
+    do_sort(&mut ByComparator::new(&mut buffer, comparator), 0, n, false, &LeafConfig::default());
+
+    let mut block = Vec::with_capacity(k);
+    loop {
+        block.clear();
+        block.extend(iter.by_ref().take(k));
+        if block.is_empty() {
+            break;
+        }
+        let m = block.len();
+        do_sort(&mut ByComparator::new(&mut block, comparator), 0, m, true, &LeafConfig::default());
+        merge_top_k(&mut buffer, &mut block, comparator);
+    }
+
+    buffer.reverse();
+    Ok(buffer)
+}
+
+/// x のうち comparator の順で先頭に来る k 要素 (0 < k <= x.len()) を、comparator の逆順で x[..k] に集める
+pub(crate) fn top_k_in_place<T, F>(x: &mut [T], k: usize, comparator: &F)
+    where F: Fn(&T, &T) -> Ordering
+{
+    let (buffer, rest) = x.split_at_mut(k);
+    do_sort(&mut ByComparator::new(buffer, comparator), 0, k, false, &LeafConfig::default());
+
+    for block in rest.chunks_mut(k) {
+        let m = block.len();
+        do_sort(&mut ByComparator::new(block, comparator), 0, m, true, &LeafConfig::default());
+        merge_top_k(buffer, block, comparator);
     }
 }
 
-fn do_sort<T, F>(x: &mut [T], forward: bool, comparator: &F)
+/// comparator の逆順に並んだ buffer と、comparator の順に並んだ block (block.len() <= buffer.len()) から、
+/// 先頭に来る buffer.len() 個を comparator の逆順で buffer に残す。残らなかった要素は block に移る
+/// 
+/// buffer[i] と block[i] の先頭に来る方を buffer に残すと、buffer は「上って下る」bitonic 列になるので、
+/// あとはマージ段 (sub_sort) を逆向きに実行するだけでよい。block が短い分は +∞ で埋めたものとみなせる
+pub(crate) fn merge_top_k<T, F>(buffer: &mut [T], block: &mut [T], comparator: &F)
     where F: Fn(&T, &T) -> Ordering
+{
+    let mut swapped = false;
+    for (a, b) in buffer.iter_mut().zip(block.iter_mut()) {
+        if comparator(b, a) == Ordering::Less {
+            std::mem::swap(a, b);
+            swapped = true;
+        }
+    }
+    // 1 つも入れ替わらなければ buffer はソート済みのまま
+    if swapped {
+        let k = buffer.len();
+        sub_sort(&mut ByComparator::new(buffer, comparator), 0, k, false);
+    }
+}
+
+fn do_sort<S>(s: &mut S, lo: usize, n: usize, forward: bool, leaf: &LeafConfig)
+    where S: SortStorage + ?Sized
 {
     // Generics を付けただけでは、以下のようなエラーが出てしまう。
     // > binary operation `>` cannot be applied to type `T`
     // 
     //
-    // [u32] はスライス。Vec とはまた異なるらしい
-    if x.len() > 1 {
-        let mid_point = x.len() / 2;
-        do_sort(&mut x[..mid_point], true, comparator);
-        do_sort(&mut x[mid_point..], false, comparator);
-        sub_sort(x, forward, comparator);
+    // スライスを分割して再帰する代わりに、storage の lo から n 要素の範囲を渡して再帰する。
+    // VecDeque や Strided のように split_at_mut できない入れ物も同じ network でソートするため
+    if n <= 1 {
+        return;
     }
+    if n <= leaf.cutoff() {
+        // 十分小さいブロックは再帰せずにまとめてソートする
+        leaf::sort_leaf(s, lo, n, leaf.sort, forward);
+        return;
+    }
+
+    // 任意長の場合は、前半を逆順・後半を正順でソートする。
+    // こうすると全体が「下って上る」bitonic 列になり、後半が短い場合でも
+    // 足りない分を番兵 (forward なら +∞) で埋めたものとみなしてマージできる
+    let mid_point = n / 2;
+    do_sort(s, lo, mid_point, !forward, leaf);
+    do_sort(s, lo + mid_point, n - mid_point, forward, leaf);
+    sub_sort(s, lo, n, forward);
 }
 
-fn sub_sort<T, F>(x: &mut [T], forward: bool, comparator: &F)
-    where F: Fn(&T, &T) -> Ordering
+fn sub_sort<S>(s: &mut S, lo: usize, n: usize, forward: bool)
+    where S: SortStorage + ?Sized
 {
-    if x.len() > 1 {
-        compare_and_swap(x, forward, comparator);
-        let mid_point = x.len() / 2;
-        sub_sort(&mut x[..mid_point], forward, comparator);
-        sub_sort(&mut x[mid_point..], forward, comparator);
+    if n > 1 {
+        // 分割位置は n 未満で最大の 2 の冪乗
+        let mid_point = greatest_power_of_two_below(n);
+        compare_and_swap(s, lo, n, mid_point, forward);
+        sub_sort(s, lo, mid_point, forward);
+        sub_sort(s, lo + mid_point, n - mid_point, forward);
     }
 }
 
-fn compare_and_swap<T, F>(x: &mut[T], forward: bool, comparator: &F)
-    where F: Fn(&T, &T) -> Ordering
+fn compare_and_swap<S>(s: &mut S, lo: usize, n: usize, mid_point: usize, forward: bool)
+    where S: SortStorage + ?Sized
 {
     let swap_condition = if forward {
         Ordering::Greater
@@ -64,25 +460,78 @@ fn compare_and_swap<T, F>(x: &mut[T], forward: bool, comparator: &F)
         Ordering::Less
     };
 
-    let mid_point = x.len() / 2;
-
-    for i in 0..mid_point {
-        if comparator(&x[i], &x[mid_point + i]) == swap_condition {
-            // x[i] = x[mid_point+i];
-            // x[mid_point] = x[i];
-            x.swap(i, mid_point+i);
+    // 比較相手 (i + mid_point) が存在する範囲だけ比較する
+    for i in lo..(lo + n - mid_point) {
+        if s.compare(i, mid_point + i) == swap_condition {
+            s.swap(i, mid_point + i);
         }
     }
 }
 
+/// 比較・交換する代わりに、比較器を記録する SortStorage
+/// 
+/// compare は常に Greater を返す。正順の比較器はその直後に swap を呼び、逆順の比較器は呼ばないので、
+/// swap が呼ばれたかどうかで比較器の向きがわかる
+struct Recorder {
+    n: usize,
+    comparators: RefCell<Vec<(usize, usize)>>,
+}
+
+impl SortStorage for Recorder {
+    fn len(&self) -> usize {
+        self.n
+    }
+
+    fn compare(&self, i: usize, j: usize) -> Ordering {
+        // ひとまず逆順 (大きい方を i に置く) の比較器として記録しておく
+        self.comparators.borrow_mut().push((j, i));
+        Ordering::Greater
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        let last = self.comparators.get_mut().last_mut().expect("swap without compare");
+        debug_assert_eq!(*last, (j, i));
+        *last = (i, j);
+    }
+}
+
+/// `Sorter` トレイトで third を呼び出すための型
+pub struct Third;
+
+impl<T: Ord> Sorter<T> for Third {
+    fn name(&self) -> &'static str {
+        "third"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { parallel: false, generic: true, power_of_two_only: false, comparator: true }
+    }
+
+    fn sort(&self, x: &mut [T], order: &SortOrder) -> Result<(), SortError> {
+        sort(x, order)
+    }
+
+    fn sort_by(&self, x: &mut [T], comparator: &(dyn Fn(&T, &T) -> Ordering + Sync)) -> Result<(), SortError> {
+        sort_by(x, &|a: &T, b: &T| comparator(a, b))
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{sort, sort_by};
+    use super::{argsort, argsort_by, sort, sort_by, sort_pairs, sort_pairs_by, sort_partial, sort_floats, sort_floats_by, sort_by_key, sort_by_cached_key, stable_sort_by, sort_by_with_leaf, sort_storage, bitonic_merge, bitonic_merge_by, merge_runs, partial_sort, top_k, bitonic_split, network};
+    use crate::leaf::{LeafConfig, LeafSort};
+    use crate::storage::{Columns, Strided};
+    use std::cell::Cell;
+    use std::collections::VecDeque;
+    use crate::{NanPolicy, SortError};
     use crate::SortOrder::{Ascending as Asc, Descending as Desc};
     use crate::utils::{
+        apply_permutation,
         new_u32_vec,
+        new_bitonic_u32_vec,
         // is_sorted_ascending,
         // is_sorted_descending,
+        is_bitonic,
         is_sorted
     };
 
@@ -90,7 +539,7 @@ mod tests {
     fn sort_u32_ascending() {
         let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
 
-        sort(&mut x, &Asc);
+        assert_eq!(sort(&mut x, &Asc), Ok(()));
 
         assert_eq!(x, vec![4, 10, 11, 20, 21, 30, 110, 330]);
     }
@@ -99,7 +548,7 @@ mod tests {
     fn sort_u32_descending() {
         let mut x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
 
-        sort(&mut x, &Desc);
+        assert_eq!(sort(&mut x, &Desc), Ok(()));
 
         assert_eq!(x, vec![330, 110, 30, 21, 20, 11, 10, 4]);
     }
@@ -108,7 +557,7 @@ mod tests {
     fn sort_str_ascending() {
         let mut x = vec!["Rust", "is", "fast", "and", "memory-efficient", "with", "no", "GC"];
 
-        sort(&mut x, &Asc);
+        assert_eq!(sort(&mut x, &Asc), Ok(()));
 
         assert_eq!(x, vec!["GC", "Rust", "and", "fast", "is", "memory-efficient", "no", "with"]);
     }
@@ -117,7 +566,7 @@ mod tests {
     fn sort_str_descending() {
         let mut x = vec!["Rust", "is", "fast", "and", "memory-efficient", "with", "no", "GC"];
 
-        sort(&mut x, &Desc);
+        assert_eq!(sort(&mut x, &Desc), Ok(()));
 
         assert_eq!(x, vec!["with", "no", "memory-efficient", "is", "fast", "and","Rust", "GC"]);
     }
@@ -225,4 +674,557 @@ mod tests {
             assert!(is_sorted(&x, &Desc));
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn sort_u32_arbitrary_length() {
+        // 2 の冪乗でない長さ (0, 1 を含む) でもソートできること
+        for &len in &[0, 1, 2, 3, 5, 7, 12, 100, 1000, 5000, 10007] {
+            let mut x = new_u32_vec(len);
+            let mut expected = x.clone();
+            expected.sort();
+
+            assert_eq!(sort(&mut x, &Asc), Ok(()));
+            assert_eq!(x, expected);
+
+            expected.reverse();
+            assert_eq!(sort(&mut x, &Desc), Ok(()));
+            assert_eq!(x, expected);
+        }
+    }
+
+    #[test]
+    fn sort_u32_with_leaf() {
+        let leaves = vec![
+            LeafConfig::disabled(),
+            LeafConfig { size: 5, sort: LeafSort::Network },
+            LeafConfig { size: 16, sort: LeafSort::Network },
+            LeafConfig { size: 64, sort: LeafSort::Network },
+            LeafConfig { size: 7, sort: LeafSort::Insertion },
+            LeafConfig { size: 64, sort: LeafSort::Insertion },
+        ];
+
+        for leaf in &leaves {
+            for &len in &[0, 1, 10, 31, 1000] {
+                let mut x = new_u32_vec(len);
+                let mut expected = x.clone();
+                expected.sort();
+
+                assert_eq!(sort_by_with_leaf(&mut x, &|a, b| a.cmp(b), leaf), Ok(()));
+                assert_eq!(x, expected);
+
+                expected.reverse();
+                assert_eq!(sort_by_with_leaf(&mut x, &|a, b| b.cmp(a), leaf), Ok(()));
+                assert_eq!(x, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn stable_sort_with_duplicate_keys() {
+        // (key, 入力順) の組を key だけで比較してソートし、同じ key の中で入力順が保たれていること
+        for &len in &[0, 1, 2, 10, 1000, 10000] {
+            let mut x: Vec<(u32, usize)> = new_u32_vec(len).into_iter()
+                .map(|v| v % 8)
+                .enumerate()
+                .map(|(i, key)| (key, i))
+                .collect();
+            let mut expected = x.clone();
+            expected.sort_by_key(|a| a.0);
+
+            assert_eq!(stable_sort_by(&mut x, &|a, b| a.0.cmp(&b.0)), Ok(()));
+            assert_eq!(x, expected);
+
+            // 降順でも同じ key の中では入力順
+            expected.sort_by_key(|a| std::cmp::Reverse(a.0));
+            let mut x = expected.clone();
+            x.sort_by_key(|&(_, i)| i);
+            assert_eq!(stable_sort_by(&mut x, &|a, b| b.0.cmp(&a.0)), Ok(()));
+            assert_eq!(x, expected);
+        }
+    }
+
+    #[test]
+    fn stable_sort_students_by_last_name() {
+        let taro = Student::new("Taro", "Yamada", 16);
+        let hanako = Student::new("Hanako", "Yamada", 14);
+        let kyoko = Student::new("Kyoko", "Ito", 15);
+        let ryosuke = Student::new("Ryosuke", "Hayashi", 17);
+
+        // Student は Clone を実装していないが、安定ソートできる
+        let mut x = vec![taro, hanako, kyoko, ryosuke];
+
+        assert_eq!(stable_sort_by(&mut x, &|a, b| a.last_name.cmp(&b.last_name)), Ok(()));
+
+        let names: Vec<&str> = x.iter().map(|s| s.first_name.as_str()).collect();
+        assert_eq!(names, vec!["Ryosuke", "Kyoko", "Taro", "Hanako"]);
+    }
+
+    #[test]
+    fn sort_str_by_key() {
+        let mut x = vec!["Rust", "is", "fast", "and", "memory-efficient", "with", "no", "GC"];
+
+        assert_eq!(sort_by_key(&mut x, &|s: &&str| s.to_lowercase()), Ok(()));
+
+        assert_eq!(x, vec!["and", "fast", "GC", "is", "memory-efficient", "no", "Rust", "with"]);
+    }
+
+    #[test]
+    fn sort_by_cached_key_calls_key_once() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let original: Vec<String> = new_u32_vec(1000).iter().map(|v| (v % 100).to_string()).collect();
+        let mut x = original.clone();
+        let calls = AtomicUsize::new(0);
+
+        assert_eq!(
+            sort_by_cached_key(&mut x, &|s: &String| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                s.parse::<u32>().unwrap()
+            }),
+            Ok(())
+        );
+
+        assert_eq!(calls.load(Ordering::SeqCst), original.len());
+        // 数値としての順序で、同じ値の中では入力順 (安定)
+        let mut expected = original;
+        expected.sort_by_key(|s| s.parse::<u32>().unwrap());
+        assert_eq!(x, expected);
+    }
+
+    #[test]
+    fn sort_f64_nan_policy() {
+        let input = vec![3.0, f64::NAN, -1.0, 0.0, f64::INFINITY, -0.0, 2.5];
+
+        let mut x = input.clone();
+        assert_eq!(sort_floats(&mut x, &Asc, NanPolicy::NanLast), Ok(()));
+        assert_eq!(&x[..6], &[-1.0, 0.0, -0.0, 2.5, 3.0, f64::INFINITY][..]);
+        assert!(x[6].is_nan());
+
+        let mut x = input.clone();
+        assert_eq!(sort_floats(&mut x, &Desc, NanPolicy::NanFirst), Ok(()));
+        assert!(x[0].is_nan());
+        assert_eq!(&x[1..], &[f64::INFINITY, 3.0, 2.5, 0.0, 0.0, -1.0][..]);
+
+        // -0.0 と +0.0 を区別する
+        let mut x = input.clone();
+        assert_eq!(sort_floats(&mut x, &Asc, NanPolicy::TotalOrder), Ok(()));
+        let bits: Vec<u64> = x[..6].iter().map(|v| v.to_bits()).collect();
+        let expected: Vec<u64> = [-1.0, -0.0, 0.0, 2.5, 3.0, f64::INFINITY].iter().map(|v: &f64| v.to_bits()).collect();
+        assert_eq!(bits, expected);
+
+        // エラーの場合は x を変更しない
+        let mut x = input.clone();
+        assert_eq!(sort_floats(&mut x, &Asc, NanPolicy::Error), Err(SortError::NaN { index: 1 }));
+        assert_eq!(x[0], 3.0);
+
+        let mut x: Vec<f32> = vec![2.0, -3.5, 1.0];
+        assert_eq!(sort_floats(&mut x, &Asc, NanPolicy::Error), Ok(()));
+        assert_eq!(x, vec![-3.5, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn sort_floats_by_field() {
+        let mut x = vec![("a", 0.5), ("b", f64::NAN), ("c", -2.0), ("d", 1.5)];
+
+        assert_eq!(sort_floats_by(&mut x, &|v: &(&str, f64)| v.1, &Asc, NanPolicy::NanLast), Ok(()));
+        let names: Vec<&str> = x.iter().map(|v| v.0).collect();
+        assert_eq!(names, vec!["c", "a", "d", "b"]);
+
+        assert_eq!(
+            sort_floats_by(&mut x, &|v: &(&str, f64)| v.1, &Asc, NanPolicy::Error),
+            Err(SortError::NaN { index: 3 })
+        );
+    }
+
+    #[test]
+    fn sort_partial_f64() {
+        let mut x = vec![2.5, -1.0, 3.0, 0.5, 10.0];
+
+        assert_eq!(sort_partial(&mut x, &Asc), Ok(()));
+        assert_eq!(x, vec![-1.0, 0.5, 2.5, 3.0, 10.0]);
+
+        assert_eq!(sort_partial(&mut x, &Desc), Ok(()));
+        assert_eq!(x, vec![10.0, 3.0, 2.5, 0.5, -1.0]);
+    }
+
+    #[test]
+    fn sort_partial_reports_incomparable_pair() {
+        let input = vec![2.5, -1.0, f64::NAN, 0.5];
+        let mut x = input.clone();
+
+        match sort_partial(&mut x, &Asc) {
+            Err(SortError::Incomparable { left, right }) => {
+                assert!(left < right);
+                assert!(x[left].is_nan() || x[right].is_nan());
+            },
+            other => panic!("unexpected result: {:?}", other),
+        }
+        // エラーの場合は x を変更しない
+        assert_eq!(&x[..2], &input[..2]);
+        assert_eq!(x[3], input[3]);
+    }
+
+    #[test]
+    fn sort_partial_sets() {
+        // 包含関係で順序付けた集合は半順序なので、比較できない組がある
+        #[derive(Debug, PartialEq)]
+        struct Set(u32);
+
+        impl PartialOrd for Set {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                use std::cmp::Ordering::*;
+                match (self.0 & other.0 == self.0, self.0 & other.0 == other.0) {
+                    (true, true) => Some(Equal),
+                    (true, false) => Some(Less),
+                    (false, true) => Some(Greater),
+                    (false, false) => None,
+                }
+            }
+        }
+
+        // 全順序になっている (包含関係の鎖) 場合はソートできる
+        let mut chain = vec![Set(0b111), Set(0b001), Set(0b011)];
+        assert_eq!(sort_partial(&mut chain, &Asc), Ok(()));
+        assert_eq!(chain, vec![Set(0b001), Set(0b011), Set(0b111)]);
+
+        let mut x = vec![Set(0b001), Set(0b010), Set(0b011)];
+        assert_eq!(sort_partial(&mut x, &Asc), Err(SortError::Incomparable { left: 0, right: 1 }));
+    }
+
+    #[test]
+    fn argsort_u32() {
+        let x: Vec<u32> = vec![10, 30, 11, 20, 4, 330, 21, 110];
+
+        let perm = argsort(&x, &Asc).unwrap();
+        assert_eq!(perm, vec![4, 0, 2, 3, 6, 1, 7, 5]);
+        // x 自体は変更されない
+        assert_eq!(x, vec![10, 30, 11, 20, 4, 330, 21, 110]);
+
+        let sorted: Vec<u32> = perm.iter().map(|&i| x[i]).collect();
+        assert_eq!(sorted, vec![4, 10, 11, 20, 21, 30, 110, 330]);
+
+        assert_eq!(argsort(&x, &Desc).unwrap(), vec![5, 7, 1, 6, 3, 2, 0, 4]);
+    }
+
+    #[test]
+    fn argsort_columns() {
+        // 複数の列を 1 つの列の順序で並べ替える
+        let ages: Vec<u8> = vec![16, 14, 15, 14];
+        let mut names = vec!["Taro", "Hanako", "Kyoko", "Ryosuke"];
+
+        let perm = argsort_by(&ages, &|a, b| a.cmp(b)).unwrap();
+        // 同じ年齢 (14) の中では元の順序
+        assert_eq!(perm, vec![1, 3, 2, 0]);
+
+        apply_permutation(&mut names, &perm);
+        assert_eq!(names, vec!["Hanako", "Ryosuke", "Kyoko", "Taro"]);
+    }
+
+    #[test]
+    fn sort_pairs_u32() {
+        for &len in &[0, 1, 2, 7, 1000] {
+            let mut keys = new_u32_vec(len);
+            // values には元の key を文字列にしたものを入れて、組が崩れていないことを確かめる
+            let mut values: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+
+            assert_eq!(sort_pairs(&mut keys, &mut values, &Desc), Ok(()));
+
+            assert!(is_sorted(&keys, &Desc));
+            for (k, v) in keys.iter().zip(values.iter()) {
+                assert_eq!(&k.to_string(), v);
+            }
+        }
+    }
+
+    #[test]
+    fn sort_pairs_by_key_column() {
+        let mut ages: Vec<u8> = vec![16, 14, 15, 17];
+        let mut names = vec!["Taro", "Hanako", "Kyoko", "Ryosuke"];
+
+        assert_eq!(sort_pairs_by(&mut ages, &mut names, &|a, b| a.cmp(b)), Ok(()));
+
+        assert_eq!(ages, vec![14, 15, 16, 17]);
+        assert_eq!(names, vec!["Hanako", "Kyoko", "Taro", "Ryosuke"]);
+    }
+
+    #[test]
+    fn sort_pairs_length_mismatch() {
+        let mut keys: Vec<u32> = vec![3, 1, 2];
+        let mut values = vec!["a", "b"];
+
+        assert_eq!(
+            sort_pairs(&mut keys, &mut values, &Asc),
+            Err(SortError::LengthMismatch { keys: 3, values: 2 })
+        );
+        assert_eq!(keys, vec![3, 1, 2]);
+    }
+
+    #[test]
+    fn sort_storage_vec_deque() {
+        for &len in &[0, 1, 2, 5, 100, 1000] {
+            let x = new_u32_vec(len);
+            // 先頭側にも要素を足して、リングバッファの中身が途中で折り返している状態にする
+            let mut deque: VecDeque<u32> = x[len / 2..].iter().copied().collect();
+            for &v in x[..len / 2].iter().rev() {
+                deque.push_front(v);
+            }
+
+            for order in &[Asc, Desc] {
+                let mut expected = x.clone();
+                assert_eq!(sort(&mut expected, order), Ok(()));
+
+                assert_eq!(sort_storage(&mut deque, order), Ok(()));
+                assert_eq!(deque.iter().copied().collect::<Vec<_>>(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn sort_storage_columns() {
+        let mut ages = vec![17u8, 14, 16, 15];
+        let mut names = vec!["Ryosuke", "Hanako", "Taro", "Kyoko"];
+        let mut scores = vec![80u32, 95, 60, 70];
+
+        let mut columns = Columns::new(&mut ages).with(&mut names).with(&mut scores);
+        assert_eq!(sort_storage(&mut columns, &Desc), Ok(()));
+
+        assert_eq!(ages, vec![17, 16, 15, 14]);
+        assert_eq!(names, vec!["Ryosuke", "Taro", "Kyoko", "Hanako"]);
+        assert_eq!(scores, vec![80, 60, 70, 95]);
+    }
+
+    #[test]
+    fn sort_storage_strided() {
+        // 3 列の行列 (行優先) の 2 列目だけをソートする
+        let mut matrix = vec![
+            1, 9, 100,
+            2, 3, 200,
+            3, 7, 300,
+            4, 1, 400,
+        ];
+        assert_eq!(sort_storage(&mut Strided::new(&mut matrix, 1, 3), &Asc), Ok(()));
+
+        assert_eq!(matrix, vec![
+            1, 1, 100,
+            2, 3, 200,
+            3, 7, 300,
+            4, 9, 400,
+        ]);
+    }
+
+    #[test]
+    fn bitonic_merge_uneven_halves() {
+        for &len in &[0, 1, 2, 3, 7, 16, 100, 1000] {
+            for &mid in &[0, 1, len / 3, len / 2, len] {
+                if mid > len {
+                    continue;
+                }
+                for order in &[Asc, Desc] {
+                    let mut x = new_u32_vec(len);
+                    assert_eq!(sort(&mut x[..mid], order), Ok(()));
+                    assert_eq!(sort(&mut x[mid..], order), Ok(()));
+                    let mut expected = x.clone();
+                    assert_eq!(sort(&mut expected, order), Ok(()));
+
+                    assert_eq!(bitonic_merge(&mut x, mid, order), Ok(()));
+                    assert_eq!(x, expected, "len = {}, mid = {}", len, mid);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bitonic_merge_students_by_age() {
+        let taro = Student::new("Taro", "Yamada", 16);
+        let hanako = Student::new("Hanako", "Yamada", 14);
+        let kyoko = Student::new("Kyoko", "Ito", 15);
+        let ryosuke = Student::new("Ryosuke", "Hayashi", 17);
+
+        let mut x = vec![&hanako, &ryosuke, &kyoko, &taro];
+        assert_eq!(bitonic_merge_by(&mut x, 2, &|a, b| a.age.cmp(&b.age)), Ok(()));
+
+        assert_eq!(x, vec![&hanako, &kyoko, &taro, &ryosuke]);
+    }
+
+    #[test]
+    fn bitonic_merge_invalid_mid() {
+        let mut x = vec![1u32, 2, 3];
+        assert_eq!(bitonic_merge(&mut x, 4, &Asc), Err(SortError::InvalidLength { len: 3 }));
+        assert_eq!(x, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn merge_many_runs() {
+        let runs = [5, 0, 17, 1, 100, 33, 2, 64, 9];
+        let len = runs.iter().sum();
+
+        for order in &[Asc, Desc] {
+            let mut x = new_u32_vec(len);
+            let mut start = 0;
+            for &run in runs.iter() {
+                assert_eq!(sort(&mut x[start..start + run], order), Ok(()));
+                start += run;
+            }
+            let mut expected = x.clone();
+            assert_eq!(sort(&mut expected, order), Ok(()));
+
+            assert_eq!(merge_runs(&mut x, &runs, order), Ok(()));
+            assert_eq!(x, expected);
+        }
+    }
+
+    #[test]
+    fn merge_runs_length_mismatch() {
+        let mut x = vec![1u32, 2, 3];
+        assert_eq!(merge_runs(&mut x, &[1, 1], &Asc), Err(SortError::InvalidLength { len: 3 }));
+        assert_eq!(merge_runs(&mut x, &[], &Asc), Err(SortError::InvalidLength { len: 3 }));
+        assert_eq!(merge_runs(&mut Vec::<u32>::new(), &[], &Asc), Ok(()));
+    }
+
+    #[test]
+    fn partial_sort_u32() {
+        for &len in &[0, 1, 2, 7, 100, 1000] {
+            for &k in &[0, 1, 3, 16, 17, 100, 5000] {
+                for order in &[Asc, Desc] {
+                    let original = new_u32_vec(len);
+                    let mut expected = original.clone();
+                    assert_eq!(sort(&mut expected, order), Ok(()));
+
+                    let mut x = original.clone();
+                    assert_eq!(partial_sort(&mut x, k, order), Ok(()));
+                    let k = k.min(len);
+                    assert_eq!(x[..k], expected[..k], "len = {}, k = {}", len, k);
+
+                    // 残りの要素も失われていない
+                    assert_eq!(sort(&mut x, order), Ok(()));
+                    assert_eq!(x, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn top_k_from_iterator() {
+        let x = new_u32_vec(1000);
+        let mut expected = x.clone();
+        assert_eq!(sort(&mut expected, &Desc), Ok(()));
+
+        for &k in &[1, 10, 64, 999, 1000] {
+            assert_eq!(top_k(x.iter().copied(), k, &|a: &u32, b: &u32| b.cmp(a)), Ok(expected[..k].to_vec()));
+        }
+        assert_eq!(top_k(x.iter(), 0, &|a: &&u32, b: &&u32| a.cmp(b)), Ok(vec![]));
+        // 要素数が k に満たない場合は全要素を返す
+        assert_eq!(top_k(vec![3, 1, 2], 5, &|a: &u32, b: &u32| a.cmp(b)), Ok(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn top_k_students_by_age() {
+        let taro = Student::new("Taro", "Yamada", 16);
+        let hanako = Student::new("Hanako", "Yamada", 14);
+        let kyoko = Student::new("Kyoko", "Ito", 15);
+        let ryosuke = Student::new("Ryosuke", "Hayashi", 17);
+
+        let x = vec![&taro, &hanako, &kyoko, &ryosuke];
+        assert_eq!(
+            top_k(x, 2, &|a: &&Student, b: &&Student| b.age.cmp(&a.age)),
+            Ok(vec![&ryosuke, &taro])
+        );
+    }
+
+    #[test]
+    fn sort_bitonic_input_same_as_full_sort() {
+        for &len in &[2, 3, 16, 17, 100, 1000] {
+            for &peak in &[0, 1, len / 3, len / 2, len - 1, len] {
+                let up_down = new_bitonic_u32_vec(len, peak);
+                let mut down_up = up_down.clone();
+                down_up[..peak].reverse();
+                down_up[peak..].reverse();
+                let duplicated: Vec<u32> = up_down.iter().map(|v| v / (u32::MAX / 4)).collect();
+
+                for input in &[up_down, down_up, duplicated] {
+                    for order in &[Asc, Desc] {
+                        let mut expected = input.clone();
+                        match *order {
+                            Asc => assert_eq!(sort_by_with_leaf(&mut expected, &|a, b| a.cmp(b), &LeafConfig::default()), Ok(())),
+                            Desc => assert_eq!(sort_by_with_leaf(&mut expected, &|a, b| b.cmp(a), &LeafConfig::default()), Ok(())),
+                        }
+
+                        let mut x = input.clone();
+                        assert_eq!(sort(&mut x, order), Ok(()));
+                        assert_eq!(x, expected, "len = {}, peak = {}", len, peak);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sort_by_skips_the_network_for_bitonic_input() {
+        let count = Cell::new(0);
+        let comparator = |a: &u32, b: &u32| {
+            count.set(count.get() + 1);
+            a.cmp(b)
+        };
+        for &len in &[0, 1, 2, 17, 100, 1000] {
+            let size = network(len, &LeafConfig::default()).unwrap().size();
+
+            // sort_by_with_leaf は入力を調べずに network だけを実行する
+            count.set(0);
+            let mut x = new_u32_vec(len);
+            assert_eq!(sort_by_with_leaf(&mut x, &comparator, &LeafConfig::default()), Ok(()));
+            assert_eq!(count.get(), size, "len = {}", len);
+
+            // ランダムな入力では、sort_by が bitonic 列かどうかの判定に使う比較は数回で済む
+            count.set(0);
+            let mut x = new_u32_vec(len);
+            assert_eq!(sort_by(&mut x, &comparator), Ok(()));
+            assert!(count.get() <= size + 8, "len = {}, comparisons = {}", len, count.get());
+
+            // bitonic 列なら、マージ段だけで済むので network より少ない比較でソートできる
+            if len > 2 {
+                count.set(0);
+                let mut x = new_bitonic_u32_vec(len, len / 2);
+                assert_eq!(sort_by(&mut x, &comparator), Ok(()));
+                assert!(count.get() < size, "len = {}, comparisons = {}", len, count.get());
+                assert!(is_sorted(&x, &Asc));
+            }
+        }
+    }
+
+    #[test]
+    fn bitonic_split_separates_halves() {
+        for &len in &[2, 5, 8, 13, 64, 100] {
+            // 下って上る列なら長さによらず分割できる
+            let mut x = new_bitonic_u32_vec(len, len / 3);
+            x[..len / 3].reverse();
+            x[len / 3..].reverse();
+            assert!(is_bitonic(&x));
+
+            let mid = bitonic_split(&mut x, &Asc).unwrap();
+            assert_eq!(mid, len.next_power_of_two() / 2);
+            let max_first = x[..mid].iter().max().unwrap();
+            assert!(x[mid..].iter().all(|v| v >= max_first), "len = {}", len);
+        }
+
+        assert_eq!(bitonic_split(&mut [1u32], &Asc), Err(SortError::InvalidLength { len: 1 }));
+    }
+
+    #[test]
+    fn comparator_panic() {
+        let mut x = new_u32_vec(1000);
+        let mut expected = x.clone();
+        expected.sort();
+
+        let result = sort_by(&mut x, &|a: &u32, b: &u32| {
+            if *a == expected[500] {
+                panic!("comparator panicked");
+            }
+            a.cmp(b)
+        });
+        assert_eq!(result, Err(SortError::ComparatorPanic));
+
+        // 要素は失われず、並べ替えただけになっている
+        x.sort();
+        assert_eq!(x, expected);
+    }
+}
@@ -1,7 +1,9 @@
 use rand::{Rng, SeedableRng};
 use rand::distributions::Standard;
 use rand_pcg::Pcg64Mcg;
-use crate::SortOrder;
+use crate::{SortError, SortOrder};
+use std::cmp::Ordering;
+use std::panic::{self, AssertUnwindSafe};
 use crate::SortOrder::{Ascending as Asc, Descending as Desc};
 
 pub fn new_u32_vec(n: usize) -> Vec<u32> {
@@ -52,4 +54,214 @@ pub fn is_sorted<T: Ord>(x: &[T], order: &SortOrder) -> bool {
         Desc => 
             x.windows(2).all(|pair| pair[1] <= pair[0]),
     }
-}
\ No newline at end of file
+}
+
+/// N 要素の配列を len 個ランダムに作る。固定長の配列を受け取るソートのテスト用
+///
+/// 配列ごとに別の値になるよう、1 つの乱数列を先頭から N 個ずつ使う
+pub fn new_u32_arrays<const N: usize>(len: usize) -> Vec<[u32; N]> {
+    let mut rng = Pcg64Mcg::from_seed([0; 16]);
+    (0..len)
+        .map(|_| {
+            let mut x = [0; N];
+            for v in x.iter_mut() {
+                *v = rng.sample(Standard);
+            }
+            x
+        })
+        .collect()
+}
+
+/// 長さ n の bitonic 列 (前半 x[..peak] が昇順、後半 x[peak..] が降順) をランダムに作る。テストやベンチマークの入力用
+/// 
+/// peak > n の場合は panic する
+pub fn new_bitonic_u32_vec(n: usize, peak: usize) -> Vec<u32> {
+    assert!(peak <= n, "peak must not be greater than n");
+    let mut x = new_u32_vec(n);
+    x[..peak].sort();
+    x[peak..].sort_by(|a, b| b.cmp(a));
+    x
+}
+
+/// x が bitonic 列 (単調増加してから単調減少する列、または単調減少してから単調増加する列) かどうか
+/// 
+/// 等しい要素が続く部分はどちらの向きとみなしてもよい。ソート済みの列も bitonic 列に含まれる。
+/// 循環シフトすると bitonic 列になる列 (例: 3, 4, 1, 2) は、任意長の bitonic network ではそのままマージできないので含めない
+pub fn is_bitonic<T: Ord>(x: &[T]) -> bool {
+    bitonic_turn(x, &|a: &T, b: &T| a.cmp(b)).is_some()
+}
+
+/// x が comparator で見て bitonic 列なら、向きが変わる位置 turn と、前半の向き (昇順なら true) を返す
+/// 
+/// x[..turn] と x[turn..] がそれぞれ単調で、向きが逆になる。単調な列の場合は turn == x.len() になる
+pub(crate) fn bitonic_turn<T, F>(x: &[T], comparator: &F) -> Option<(usize, bool)>
+    where F: Fn(&T, &T) -> Ordering
+{
+    // 最初に Equal 以外になった比較の結果が、前半の向き
+    let mut direction = Ordering::Equal;
+    let mut turn = x.len();
+    for i in 1..x.len() {
+        match comparator(&x[i - 1], &x[i]) {
+            Ordering::Equal => {},
+            ordering if direction == Ordering::Equal => direction = ordering,
+            ordering if ordering != direction => {
+                turn = i;
+                break;
+            },
+            _ => {},
+        }
+    }
+
+    // 後半は前半と逆向き (または Equal) の比較しか出てこないこと
+    let monotone = x[turn..].windows(2)
+        .all(|pair| comparator(&pair[0], &pair[1]) != direction);
+    if monotone {
+        Some((turn, direction != Ordering::Greater))
+    } else {
+        None
+    }
+}
+
+/// n 未満で最大の 2 の冪乗を返す。任意長の bitonic network の分割位置に使う
+/// 
+/// n が 2 未満の場合は分割できないので、呼び出し側で n >= 2 を保証すること
+pub fn greatest_power_of_two_below(n: usize) -> usize {
+    debug_assert!(n >= 2);
+    n.next_power_of_two() / 2
+}
+
+/// f を実行し、途中で panic したら `SortError::ComparatorPanic` を返す
+/// 
+/// ソートの中で panic しうるのは利用者が渡した comparator だけなので、その panic をエラーとして返すために使う。
+/// 要素は swap でしか動かさないので、panic した時点でもスライスは元の要素の並べ替えになっている
+pub(crate) fn catch_comparator_panic<R, F: FnOnce() -> R>(f: F) -> Result<R, SortError> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|_| SortError::ComparatorPanic)
+}
+
+/// perm に従って x を並べ替える。並べ替えた後の x[i] は、元の x[perm[i]] になる
+/// 
+/// 巡回置換ごとに swap していくので、T: Clone は不要。perm が 0..x.len() の置換でない場合は panic する
+pub fn apply_permutation<T>(x: &mut [T], perm: &[usize]) {
+    assert_eq!(x.len(), perm.len(), "the permutation must have the same length as x");
+
+    let mut visited = vec![false; perm.len()];
+    for start in 0..perm.len() {
+        if visited[start] {
+            continue;
+        }
+        // start から始まる巡回を辿る。x[current] に x[perm[current]] を持ってくると、
+        // 元の x[start] が perm[current] の位置に押し出されるので、次はそこを埋める
+        let mut current = start;
+        loop {
+            visited[current] = true;
+            let next = perm[current];
+            if next == start {
+                break;
+            }
+            assert!(!visited[next], "perm is not a permutation");
+            x.swap(current, next);
+            current = next;
+        }
+    }
+}
+
+/// 置換 perm の逆置換を返す。inverse[perm[i]] == i になる
+/// 
+/// argsort の結果に対して使うと、元の各要素がソート後に何番目に来るかがわかる
+pub fn invert_permutation(perm: &[usize]) -> Vec<usize> {
+    let mut inverse = vec![usize::MAX; perm.len()];
+    for (i, &p) in perm.iter().enumerate() {
+        assert!(p < perm.len() && inverse[p] == usize::MAX, "perm is not a permutation");
+        inverse[p] = i;
+    }
+    inverse
+}
+
+/// argsort の結果 perm から、x の各要素の順位 (0 始まり) を返す
+/// 
+/// 等しい要素には同じ順位 (そのうち最も小さいもの) を付ける。例えば [10, 20, 10, 30] の昇順の順位は [0, 2, 0, 3]
+pub fn ranks<T: PartialEq>(x: &[T], perm: &[usize]) -> Vec<usize> {
+    assert_eq!(x.len(), perm.len(), "the permutation must have the same length as x");
+
+    let mut ranks = vec![0; x.len()];
+    for (position, &i) in perm.iter().enumerate() {
+        ranks[i] = if position > 0 && x[perm[position - 1]] == x[i] {
+            ranks[perm[position - 1]]
+        } else {
+            position
+        };
+    }
+    ranks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_permutation, invert_permutation, is_bitonic, new_bitonic_u32_vec, new_u32_arrays, new_u32_vec, ranks};
+
+    #[test]
+    fn u32_arrays() {
+        let arrays = new_u32_arrays::<4>(3);
+        assert_eq!(arrays.len(), 3);
+        assert_eq!(arrays.concat(), new_u32_vec(12));
+        assert_eq!(new_u32_arrays::<0>(2), vec![[]; 2]);
+    }
+
+    #[test]
+    fn apply_permutation_in_place() {
+        let mut x = vec!["a", "b", "c", "d", "e"];
+        apply_permutation(&mut x, &[3, 0, 4, 1, 2]);
+        assert_eq!(x, vec!["d", "a", "e", "b", "c"]);
+
+        let mut empty: Vec<u32> = vec![];
+        apply_permutation(&mut empty, &[]);
+    }
+
+    #[test]
+    #[should_panic(expected = "perm is not a permutation")]
+    fn apply_permutation_rejects_duplicates() {
+        let mut x = vec![1, 2, 3];
+        apply_permutation(&mut x, &[1, 1, 0]);
+    }
+
+    #[test]
+    fn invert() {
+        let perm = vec![3, 0, 4, 1, 2];
+        let inverse = invert_permutation(&perm);
+        assert_eq!(inverse, vec![1, 3, 4, 0, 2]);
+        assert_eq!(invert_permutation(&inverse), perm);
+    }
+
+    #[test]
+    fn ranks_with_ties() {
+        let x = vec![10, 20, 10, 30];
+        // 昇順の argsort
+        let perm = vec![0, 2, 1, 3];
+        assert_eq!(ranks(&x, &perm), vec![0, 2, 0, 3]);
+    }
+
+    #[test]
+    fn bitonic_sequences() {
+        assert!(is_bitonic::<u32>(&[]));
+        assert!(is_bitonic(&[1]));
+        assert!(is_bitonic(&[1, 2, 3]));
+        assert!(is_bitonic(&[3, 2, 1]));
+        assert!(is_bitonic(&[1, 3, 5, 4, 2]));
+        assert!(is_bitonic(&[5, 3, 1, 2, 4]));
+        assert!(is_bitonic(&[2, 2, 3, 3, 1, 1]));
+
+        assert!(!is_bitonic(&[1, 3, 2, 4]));
+        // 循環シフトすると bitonic 列になる列は含めない
+        assert!(!is_bitonic(&[3, 4, 1, 2]));
+    }
+
+    #[test]
+    fn new_bitonic_vec() {
+        for &(n, peak) in &[(0, 0), (1, 0), (1, 1), (100, 0), (100, 37), (100, 100)] {
+            let x = new_bitonic_u32_vec(n, peak);
+            assert_eq!(x.len(), n);
+            assert!(is_bitonic(&x));
+            assert!(x[..peak].windows(2).all(|w| w[0] <= w[1]));
+            assert!(x[peak..].windows(2).all(|w| w[0] >= w[1]));
+        }
+    }
+}
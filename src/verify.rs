@@ -0,0 +1,161 @@
+use crate::network::Network;
+use rayon::prelude::*;
+use std::fmt;
+
+// 0-1 原理: 比較器だけでできたネットワークが 0 と 1 だけからなる 2^n 通りの入力をすべてソートできれば、
+// 任意の入力をソートできる。そこで 2^n 通りの入力を実際にネットワークに通して確かめる。
+//
+// 入力は 64 通りずつまとめて u64 のビットで表す (ビット lane が 64 通りのうち lane 番目の入力)。
+// 0-1 の比較器は min = AND、max = OR なので、1 回のビット演算で 64 通りの入力を同時に処理できる。
+// 64 通りずつのまとまり同士は独立しているので、rayon で並列に調べる。
+
+/// verify で扱える最大の入力数。2^n 通りを調べるので、これより大きいと現実的な時間で終わらない
+pub const MAX_VERIFY_SIZE: usize = 30;
+
+/// ネットワークがソートできなかった 0-1 入力
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counterexample {
+    pub input: Vec<u8>,
+    /// input をネットワークに通した結果 (昇順になっていない)
+    pub output: Vec<u8>,
+}
+
+impl fmt::Display for Counterexample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let to_string = |bits: &[u8]| bits.iter().map(|b| b.to_string()).collect::<String>();
+        write!(f, "input {} is not sorted: {}", to_string(&self.input), to_string(&self.output))
+    }
+}
+
+/// network が、0 と 1 だけからなるすべての入力を昇順にソートできるか調べる
+///
+/// ソートできない入力があれば、そのうち入力を 2 進数 (input[i] が i ビット目) とみなして最も小さいものを返す。
+/// network.n() > MAX_VERIFY_SIZE の場合は panic する
+pub fn verify(network: &Network) -> Result<(), Counterexample> {
+    let n = network.n();
+    assert!(n <= MAX_VERIFY_SIZE, "cannot verify a network with more than {} inputs", MAX_VERIFY_SIZE);
+
+    // n <= 6 でも 1 つのまとまりは作る (余ったビットは同じ入力を重複して調べるだけ)
+    let blocks = 1u64 << n.saturating_sub(6);
+    let comparators: Vec<(usize, usize)> = network.comparators().collect();
+
+    let failure = (0..blocks).into_par_iter()
+        .find_map_first(|block| {
+            let mut wires: Vec<u64> = (0..n).map(|i| wire(block, i)).collect();
+            for &(i, j) in comparators.iter() {
+                let (a, b) = (wires[i], wires[j]);
+                wires[i] = a & b;
+                wires[j] = a | b;
+            }
+            // 昇順になっていない (前の線が 1 で次の線が 0) 入力のビット
+            let unsorted = wires.windows(2).fold(0, |acc, pair| acc | (pair[0] & !pair[1]));
+            if unsorted == 0 {
+                None
+            } else {
+                Some((block << 6) | u64::from(unsorted.trailing_zeros()))
+            }
+        });
+
+    match failure {
+        None => Ok(()),
+        Some(bits) => {
+            let input: Vec<u8> = (0..n).map(|i| ((bits >> i) & 1) as u8).collect();
+            let mut output = input.clone();
+            network.apply(&mut output, &|a, b| a.cmp(b)).unwrap();
+            Err(Counterexample { input, output })
+        },
+    }
+}
+
+/// block 番目のまとまりの 64 通りの入力について、線 i の値を並べたビット列
+fn wire(block: u64, i: usize) -> u64 {
+    // 入力の番号は block * 64 + lane。下位 6 ビットは lane で決まり、それ以上は block で決まる
+    const LANE_BITS: [u64; 6] = [
+        0xAAAA_AAAA_AAAA_AAAA,
+        0xCCCC_CCCC_CCCC_CCCC,
+        0xF0F0_F0F0_F0F0_F0F0,
+        0xFF00_FF00_FF00_FF00,
+        0xFFFF_0000_FFFF_0000,
+        0xFFFF_FFFF_0000_0000,
+    ];
+    if i < 6 {
+        LANE_BITS[i]
+    } else if (block >> (i - 6)) & 1 == 1 {
+        u64::MAX
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify, Counterexample};
+    use crate::leaf::{self, LeafConfig, LeafSort, MAX_NETWORK_SIZE};
+    use crate::network::Network;
+    use crate::{iterative, third, SortError};
+
+    #[test]
+    fn bitonic_networks_of_third() {
+        let leaves = [
+            LeafConfig::disabled(),
+            LeafConfig::default(),
+            LeafConfig { size: 5, sort: LeafSort::Network },
+        ];
+        for n in 0..=18 {
+            for leaf in leaves.iter() {
+                let network = third::network(n, leaf).unwrap();
+                assert_eq!(verify(&network), Ok(()), "n = {}, leaf = {:?}", n, leaf);
+            }
+        }
+        // leaf なしの third は Network::bitonic と同じネットワーク
+        assert_eq!(third::network(13, &LeafConfig::disabled()), Ok(Network::bitonic(13)));
+        assert_eq!(
+            third::network(13, &LeafConfig { size: 8, sort: LeafSort::Insertion }),
+            Err(SortError::Unsupported { sorter: "third" })
+        );
+    }
+
+    #[test]
+    fn large_bitonic_network() {
+        assert_eq!(verify(&third::network(22, &LeafConfig::default()).unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn iterative_networks() {
+        for n in 0..=18 {
+            assert_eq!(verify(&iterative::network(n)), Ok(()), "n = {}", n);
+        }
+    }
+
+    #[test]
+    fn hard_coded_leaf_networks() {
+        for n in 0..=MAX_NETWORK_SIZE {
+            assert_eq!(verify(&Network::from_comparators(n, leaf::network(n))), Ok(()), "n = {}", n);
+        }
+    }
+
+    #[test]
+    fn odd_even_and_pairwise_networks() {
+        for n in 0..=18 {
+            assert_eq!(verify(&Network::odd_even_merge(n)), Ok(()), "n = {}", n);
+            assert_eq!(verify(&Network::pairwise(n)), Ok(()), "n = {}", n);
+        }
+    }
+
+    #[test]
+    fn broken_network() {
+        // 最後の比較器を取り除くと、真ん中の 2 つが入れ替わったままの入力が残る
+        let comparators: Vec<(usize, usize)> = leaf::network(4).collect();
+        let broken = Network::from_comparators(4, comparators[..comparators.len() - 1].iter().copied());
+
+        let failure = verify(&broken).unwrap_err();
+        assert_eq!(failure, Counterexample { input: vec![1, 0, 1, 0], output: vec![0, 1, 0, 1] });
+        assert_eq!(failure.to_string(), "input 1010 is not sorted: 0101");
+
+        // 7 入力以上 (複数のまとまり) でも見つかる
+        let comparators: Vec<(usize, usize)> = leaf::network(12).collect();
+        let broken = Network::from_comparators(12, comparators[1..].iter().copied());
+        let failure = verify(&broken).unwrap_err();
+        assert!(failure.output.windows(2).any(|pair| pair[0] > pair[1]));
+    }
+}
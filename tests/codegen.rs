@@ -0,0 +1,48 @@
+// sortgen で生成して generated/networks.rs に置いた Rust のコードが、
+// 今の生成器の出力と一致していることと、third::sort と同じ結果になることを確かめる
+
+#[path = "generated/networks.rs"]
+mod networks;
+
+use rust_sort::codegen::{generate, Kind, Language};
+use rust_sort::third;
+use rust_sort::utils::new_u32_arrays;
+use rust_sort::SortOrder;
+
+use std::convert::TryInto;
+
+const GENERATED: &str = include_str!("generated/networks.rs");
+
+#[test]
+fn generated_source_is_up_to_date() {
+    let specs = [(4, Kind::Bitonic), (8, Kind::OddEvenMerge), (13, Kind::Bitonic), (16, Kind::Pairwise)];
+    for &(n, kind) in specs.iter() {
+        let name = format!("{}_{}", kind.name(), n);
+        let code = generate(&kind.network(n), Language::Rust, &name, "");
+        assert!(GENERATED.contains(&code), "{} is out of date. run: cargo run --bin sortgen -- {} {} rust", name, n, kind.name());
+    }
+}
+
+/// 生成した関数 sort で、長さ N の入力をいくつか third::sort と同じ順に並べられることを確かめる
+fn check<const N: usize>(sort: fn(&mut [u32; N])) {
+    // 0-1 の入力をすべてと、ランダムな入力
+    let mut inputs: Vec<Vec<u32>> = (0..(1u32 << N)).map(|bits| (0..N).map(|i| (bits >> i) & 1).collect()).collect();
+    inputs.extend(new_u32_arrays::<N>(100).iter().map(|x| x.to_vec()));
+
+    for input in inputs {
+        let mut expected = input.clone();
+        assert_eq!(third::sort(&mut expected, &SortOrder::Ascending), Ok(()));
+
+        let mut x: [u32; N] = input.as_slice().try_into().unwrap();
+        sort(&mut x);
+        assert_eq!(x.to_vec(), expected);
+    }
+}
+
+#[test]
+fn generated_networks_same_as_third() {
+    check(networks::bitonic_4);
+    check(networks::odd_even_merge_8);
+    check(networks::bitonic_13);
+    check(networks::pairwise_16);
+}
@@ -0,0 +1,192 @@
+// sortgen で生成したコード。手で編集せず、以下のコマンドで作り直すこと
+//
+//   cargo run --bin sortgen -- 4 bitonic rust
+//   cargo run --bin sortgen -- 8 odd_even_merge rust
+//   cargo run --bin sortgen -- 13 bitonic rust
+//   cargo run --bin sortgen -- 16 pairwise rust
+
+/// 4 要素のソーティングネットワーク (比較器 6 個、3 段)。sortgen で生成
+pub fn bitonic_4<T: Ord>(x: &mut [T; 4]) {
+    // 1 段目
+    if x[0] < x[1] { x.swap(1, 0); }
+    if x[3] < x[2] { x.swap(2, 3); }
+    // 2 段目
+    if x[2] < x[0] { x.swap(0, 2); }
+    if x[3] < x[1] { x.swap(1, 3); }
+    // 3 段目
+    if x[1] < x[0] { x.swap(0, 1); }
+    if x[3] < x[2] { x.swap(2, 3); }
+}
+
+/// 8 要素のソーティングネットワーク (比較器 19 個、6 段)。sortgen で生成
+pub fn odd_even_merge_8<T: Ord>(x: &mut [T; 8]) {
+    // 1 段目
+    if x[1] < x[0] { x.swap(0, 1); }
+    if x[3] < x[2] { x.swap(2, 3); }
+    if x[5] < x[4] { x.swap(4, 5); }
+    if x[7] < x[6] { x.swap(6, 7); }
+    // 2 段目
+    if x[2] < x[0] { x.swap(0, 2); }
+    if x[3] < x[1] { x.swap(1, 3); }
+    if x[6] < x[4] { x.swap(4, 6); }
+    if x[7] < x[5] { x.swap(5, 7); }
+    // 3 段目
+    if x[2] < x[1] { x.swap(1, 2); }
+    if x[6] < x[5] { x.swap(5, 6); }
+    if x[4] < x[0] { x.swap(0, 4); }
+    if x[7] < x[3] { x.swap(3, 7); }
+    // 4 段目
+    if x[5] < x[1] { x.swap(1, 5); }
+    if x[6] < x[2] { x.swap(2, 6); }
+    // 5 段目
+    if x[4] < x[2] { x.swap(2, 4); }
+    if x[5] < x[3] { x.swap(3, 5); }
+    // 6 段目
+    if x[2] < x[1] { x.swap(1, 2); }
+    if x[4] < x[3] { x.swap(3, 4); }
+    if x[6] < x[5] { x.swap(5, 6); }
+}
+
+/// 13 要素のソーティングネットワーク (比較器 53 個、10 段)。sortgen で生成
+pub fn bitonic_13<T: Ord>(x: &mut [T; 13]) {
+    // 1 段目
+    if x[2] < x[1] { x.swap(1, 2); }
+    if x[4] < x[5] { x.swap(5, 4); }
+    if x[7] < x[8] { x.swap(8, 7); }
+    if x[9] < x[10] { x.swap(10, 9); }
+    if x[12] < x[11] { x.swap(11, 12); }
+    // 2 段目
+    if x[2] < x[0] { x.swap(0, 2); }
+    if x[3] < x[5] { x.swap(5, 3); }
+    if x[6] < x[8] { x.swap(8, 6); }
+    if x[11] < x[9] { x.swap(9, 11); }
+    if x[12] < x[10] { x.swap(10, 12); }
+    // 3 段目
+    if x[1] < x[0] { x.swap(0, 1); }
+    if x[3] < x[4] { x.swap(4, 3); }
+    if x[6] < x[7] { x.swap(7, 6); }
+    if x[10] < x[9] { x.swap(9, 10); }
+    if x[12] < x[11] { x.swap(11, 12); }
+    // 4 段目
+    if x[0] < x[4] { x.swap(4, 0); }
+    if x[1] < x[5] { x.swap(5, 1); }
+    if x[10] < x[6] { x.swap(6, 10); }
+    if x[11] < x[7] { x.swap(7, 11); }
+    if x[12] < x[8] { x.swap(8, 12); }
+    // 5 段目
+    if x[0] < x[2] { x.swap(2, 0); }
+    if x[1] < x[3] { x.swap(3, 1); }
+    if x[4] < x[5] { x.swap(5, 4); }
+    if x[8] < x[6] { x.swap(6, 8); }
+    if x[9] < x[7] { x.swap(7, 9); }
+    if x[12] < x[10] { x.swap(10, 12); }
+    // 6 段目
+    if x[0] < x[1] { x.swap(1, 0); }
+    if x[2] < x[3] { x.swap(3, 2); }
+    if x[7] < x[6] { x.swap(6, 7); }
+    if x[9] < x[8] { x.swap(8, 9); }
+    if x[11] < x[10] { x.swap(10, 11); }
+    if x[12] < x[4] { x.swap(4, 12); }
+    // 7 段目
+    if x[8] < x[0] { x.swap(0, 8); }
+    if x[9] < x[1] { x.swap(1, 9); }
+    if x[10] < x[2] { x.swap(2, 10); }
+    if x[11] < x[3] { x.swap(3, 11); }
+    // 8 段目
+    if x[4] < x[0] { x.swap(0, 4); }
+    if x[5] < x[1] { x.swap(1, 5); }
+    if x[6] < x[2] { x.swap(2, 6); }
+    if x[7] < x[3] { x.swap(3, 7); }
+    if x[12] < x[8] { x.swap(8, 12); }
+    if x[11] < x[9] { x.swap(9, 11); }
+    // 9 段目
+    if x[2] < x[0] { x.swap(0, 2); }
+    if x[3] < x[1] { x.swap(1, 3); }
+    if x[6] < x[4] { x.swap(4, 6); }
+    if x[7] < x[5] { x.swap(5, 7); }
+    if x[10] < x[8] { x.swap(8, 10); }
+    // 10 段目
+    if x[1] < x[0] { x.swap(0, 1); }
+    if x[3] < x[2] { x.swap(2, 3); }
+    if x[5] < x[4] { x.swap(4, 5); }
+    if x[7] < x[6] { x.swap(6, 7); }
+    if x[9] < x[8] { x.swap(8, 9); }
+    if x[11] < x[10] { x.swap(10, 11); }
+}
+
+/// 16 要素のソーティングネットワーク (比較器 63 個、10 段)。sortgen で生成
+pub fn pairwise_16<T: Ord>(x: &mut [T; 16]) {
+    // 1 段目
+    if x[1] < x[0] { x.swap(0, 1); }
+    if x[3] < x[2] { x.swap(2, 3); }
+    if x[5] < x[4] { x.swap(4, 5); }
+    if x[7] < x[6] { x.swap(6, 7); }
+    if x[9] < x[8] { x.swap(8, 9); }
+    if x[11] < x[10] { x.swap(10, 11); }
+    if x[13] < x[12] { x.swap(12, 13); }
+    if x[15] < x[14] { x.swap(14, 15); }
+    // 2 段目
+    if x[2] < x[0] { x.swap(0, 2); }
+    if x[3] < x[1] { x.swap(1, 3); }
+    if x[6] < x[4] { x.swap(4, 6); }
+    if x[7] < x[5] { x.swap(5, 7); }
+    if x[10] < x[8] { x.swap(8, 10); }
+    if x[11] < x[9] { x.swap(9, 11); }
+    if x[14] < x[12] { x.swap(12, 14); }
+    if x[15] < x[13] { x.swap(13, 15); }
+    // 3 段目
+    if x[4] < x[0] { x.swap(0, 4); }
+    if x[5] < x[1] { x.swap(1, 5); }
+    if x[6] < x[2] { x.swap(2, 6); }
+    if x[7] < x[3] { x.swap(3, 7); }
+    if x[12] < x[8] { x.swap(8, 12); }
+    if x[13] < x[9] { x.swap(9, 13); }
+    if x[14] < x[10] { x.swap(10, 14); }
+    if x[15] < x[11] { x.swap(11, 15); }
+    // 4 段目
+    if x[8] < x[0] { x.swap(0, 8); }
+    if x[9] < x[1] { x.swap(1, 9); }
+    if x[10] < x[2] { x.swap(2, 10); }
+    if x[11] < x[3] { x.swap(3, 11); }
+    if x[12] < x[4] { x.swap(4, 12); }
+    if x[13] < x[5] { x.swap(5, 13); }
+    if x[14] < x[6] { x.swap(6, 14); }
+    if x[15] < x[7] { x.swap(7, 15); }
+    // 5 段目
+    if x[8] < x[4] { x.swap(4, 8); }
+    if x[9] < x[5] { x.swap(5, 9); }
+    if x[10] < x[6] { x.swap(6, 10); }
+    if x[11] < x[7] { x.swap(7, 11); }
+    // 6 段目
+    if x[8] < x[2] { x.swap(2, 8); }
+    if x[9] < x[3] { x.swap(3, 9); }
+    if x[12] < x[6] { x.swap(6, 12); }
+    if x[13] < x[7] { x.swap(7, 13); }
+    // 7 段目
+    if x[4] < x[2] { x.swap(2, 4); }
+    if x[5] < x[3] { x.swap(3, 5); }
+    if x[8] < x[6] { x.swap(6, 8); }
+    if x[9] < x[7] { x.swap(7, 9); }
+    if x[12] < x[10] { x.swap(10, 12); }
+    if x[13] < x[11] { x.swap(11, 13); }
+    // 8 段目
+    if x[8] < x[1] { x.swap(1, 8); }
+    if x[10] < x[3] { x.swap(3, 10); }
+    if x[12] < x[5] { x.swap(5, 12); }
+    if x[14] < x[7] { x.swap(7, 14); }
+    // 9 段目
+    if x[4] < x[1] { x.swap(1, 4); }
+    if x[8] < x[5] { x.swap(5, 8); }
+    if x[12] < x[9] { x.swap(9, 12); }
+    if x[6] < x[3] { x.swap(3, 6); }
+    if x[10] < x[7] { x.swap(7, 10); }
+    if x[14] < x[11] { x.swap(11, 14); }
+    // 10 段目
+    if x[2] < x[1] { x.swap(1, 2); }
+    if x[4] < x[3] { x.swap(3, 4); }
+    if x[6] < x[5] { x.swap(5, 6); }
+    if x[8] < x[7] { x.swap(7, 8); }
+    if x[10] < x[9] { x.swap(9, 10); }
+    if x[12] < x[11] { x.swap(11, 12); }
+    if x[14] < x[13] { x.swap(13, 14); }
+}